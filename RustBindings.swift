@@ -25,14 +25,14 @@ class ClipboardManagerFFI {
         let cName = name.cString(using: .utf8)!
         let cShortcut = shortcut.cString(using: .utf8)!
         
-        return clipboard_manager_add_register(handle, cName, cShortcut) != 0
+        return clipboard_manager_add_register(handle, cName, cShortcut) == 0
     }
     
     func updateRegisterContent(name: String, content: String) -> Bool {
         let cName = name.cString(using: .utf8)!
         let cContent = content.cString(using: .utf8)!
         
-        return clipboard_manager_update_register_content(handle, cName, cContent) != 0
+        return clipboard_manager_update_register_content(handle, cName, cContent) == 0
     }
     
     func getRegisterContent(name: String) -> String? {
@@ -49,14 +49,14 @@ class ClipboardManagerFFI {
     func removeRegister(name: String) -> Bool {
         let cName = name.cString(using: .utf8)!
         
-        return clipboard_manager_remove_register(handle, cName) != 0
+        return clipboard_manager_remove_register(handle, cName) == 0
     }
     
     func updateShortcut(name: String, shortcut: String) -> Bool {
         let cName = name.cString(using: .utf8)!
         let cShortcut = shortcut.cString(using: .utf8)!
         
-        return clipboard_manager_update_shortcut(handle, cName, cShortcut) != 0
+        return clipboard_manager_update_shortcut(handle, cName, cShortcut) == 0
     }
     
     func getAllRegisters() -> [ClipboardRegister] {
@@ -1,338 +1,6772 @@
 
 // clipboard_manager/src/main.rs
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError, TryLockResult};
 use serde::{Serialize, Deserialize};
+use std::fmt;
 use std::fs;
-use std::path::PathBuf;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::thread::{self, JoinHandle};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use dirs;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::aead::rand_core::RngCore;
+use argon2::Argon2;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use blake2::{Blake2s256, Digest};
+use unicode_normalization::{is_nfc, UnicodeNormalization};
+use unicode_normalization::char::is_combining_mark;
+use chrono::{DateTime, Local};
+use chrono::format::{Item, StrftimeItems};
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct ClipboardRegister {
     pub content: String,
     pub shortcut: String,
+    // Unix timestamps in seconds; None for registers saved before these existed
+    #[serde(default)]
+    pub created_at: Option<u64>,
+    #[serde(default)]
+    pub updated_at: Option<u64>,
+    // Previous contents, oldest first, bounded by ClipboardState::history_limit
+    #[serde(default)]
+    pub history: Vec<String>,
+    // MIME type of `content`. Anything other than text/plain is stored
+    // base64-encoded. Omitted from JSON for text so plain registers keep
+    // their original on-disk shape.
+    #[serde(default = "default_content_type", skip_serializing_if = "is_plain_text")]
+    pub content_type: String,
+    // Kept sorted and free of duplicates
+    #[serde(default)]
+    pub tags: Vec<String>,
+    // Pinned registers are listed before all others
+    #[serde(default)]
+    pub pinned: bool,
+    // Unix seconds of the last mark_used; None if never used
+    #[serde(default)]
+    pub last_used_at: Option<u64>,
+    // Number of mark_used calls
+    #[serde(default)]
+    pub use_count: u64,
+    // Locked registers refuse content and shortcut changes and removal
+    #[serde(default)]
+    pub locked: bool,
+    // What the content is written in, e.g. "json", "shell" or "markdown".
+    // Only "json" is checked by validate_register; anything else is kept as
+    // a hint for the UI.
+    #[serde(default)]
+    pub format: Option<String>,
+    // Unix seconds after which purge_expired removes the register; None
+    // never expires
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+    // Hidden registers (e.g. API keys) are left out of get_all_registers and
+    // its pages, but can still be read by name
+    #[serde(default)]
+    pub hidden: bool,
+}
+
+pub const TEXT_PLAIN: &str = "text/plain";
+
+fn default_content_type() -> String {
+    TEXT_PLAIN.to_string()
+}
+
+fn is_plain_text(content_type: &str) -> bool {
+    content_type == TEXT_PLAIN
+}
+
+impl ClipboardRegister {
+    pub fn new(content: String, shortcut: String) -> Self {
+        ClipboardRegister::new_at(content, shortcut, now_secs())
+    }
+    
+    // A new register created at Unix second `now`
+    pub fn new_at(content: String, shortcut: String, now: u64) -> Self {
+        ClipboardRegister {
+            content,
+            shortcut,
+            created_at: Some(now),
+            updated_at: Some(now),
+            history: Vec::new(),
+            content_type: default_content_type(),
+            tags: Vec::new(),
+            pinned: false,
+            last_used_at: None,
+            use_count: 0,
+            locked: false,
+            format: None,
+            expires_at: None,
+            hidden: false,
+        }
+    }
+    
+    pub fn is_binary(&self) -> bool {
+        !is_plain_text(&self.content_type)
+    }
+    
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|at| now >= at)
+    }
+    
+    // Replaces the content with text, pushing the previous text onto the
+    // history. History only holds text, so binary payloads are never pushed.
+    fn set_text_content(&mut self, content: String, history_limit: usize, now: u64) {
+        let was_binary = self.is_binary();
+        let previous = std::mem::replace(&mut self.content, content);
+        if !previous.is_empty() && !was_binary {
+            self.history.push(previous);
+        }
+        self.content_type = default_content_type();
+        trim_history(&mut self.history, history_limit);
+        self.updated_at = Some(now);
+    }
+    
+    // Hex BLAKE2s-256 of the content as stored (base64 text for binary
+    // registers). Computed on each call rather than stored, so it can never
+    // go stale, and identical content hashes the same on every machine.
+    pub fn content_hash(&self) -> String {
+        Blake2s256::digest(self.content.as_bytes()).iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+    
+    // Roughly how many bytes this register takes in a compact config file:
+    // the variable-length fields plus a fixed allowance for keys, numbers
+    // and punctuation. Ignores JSON escaping.
+    pub fn estimated_json_size(&self) -> usize {
+        let quoted = |s: &String| s.len() + 3;
+        let mut size = REGISTER_JSON_OVERHEAD + self.content.len() + self.shortcut.len();
+        size += self.history.iter().map(quoted).sum::<usize>();
+        size += self.tags.iter().map(quoted).sum::<usize>();
+        size += self.format.as_ref().map_or(0, String::len);
+        if !is_plain_text(&self.content_type) {
+            size += self.content_type.len() + 20;
+        }
+        size
+    }
+    
+    // Returns false if the tag was already present
+    pub fn add_tag(&mut self, tag: &str) -> bool {
+        match self.tags.binary_search_by(|t| t.as_str().cmp(tag)) {
+            Ok(_) => false,
+            Err(index) => {
+                self.tags.insert(index, tag.to_string());
+                true
+            }
+        }
+    }
+    
+    // Returns false if the tag was not present
+    pub fn remove_tag(&mut self, tag: &str) -> bool {
+        match self.tags.binary_search_by(|t| t.as_str().cmp(tag)) {
+            Ok(index) => {
+                self.tags.remove(index);
+                true
+            },
+            Err(_) => false
+        }
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// Source of "now" for timestamps, TTLs and date tokens, so they can be
+// pinned to known values
+pub trait Clock: fmt::Debug + Send + Sync {
+    // Unix time in seconds
+    fn now_secs(&self) -> u64;
+}
+
+// The system's wall clock
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_secs(&self) -> u64 {
+        now_secs()
+    }
+}
+
+fn system_clock() -> Arc<dyn Clock> {
+    Arc::new(SystemClock)
+}
+
+// A clock that only moves when told to; share it with Arc to keep a handle
+// for set and advance after giving it to a state
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    secs: AtomicU64,
+}
+
+impl ManualClock {
+    pub fn new(secs: u64) -> Self {
+        ManualClock { secs: AtomicU64::new(secs) }
+    }
+    
+    pub fn set(&self, secs: u64) {
+        self.secs.store(secs, Ordering::SeqCst);
+    }
+    
+    pub fn advance(&self, secs: u64) {
+        self.secs.fetch_add(secs, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_secs(&self) -> u64 {
+        self.secs.load(Ordering::SeqCst)
+    }
+}
+
+// Error codes shared with Swift. Every FFI function that returns a c_int
+// returns CLIPBOARD_OK (0) on success or one of these (negative) values.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClipboardError {
+    NullPointer = -1,
+    InvalidUtf8 = -2,
+    DuplicateName = -3,
+    NotFound = -4,
+    ContentTooLarge = -5,
+    EmptyHistory = -6,
+    ShortcutInUse = -7,
+    Io = -8,
+    InvalidFormat = -9,
+    Internal = -10,
+    InvalidShortcut = -11,
+    InvalidArgument = -12,
+    InvalidName = -13,
+    ActiveProfile = -14,
+    SaveFailed = -15,
+    Locked = -16,
+    RegisterLimit = -17,
+    Busy = -18,
+    Conflict = -19,
+    Destroyed = -20,
+}
+
+pub const CLIPBOARD_OK: i32 = 0;
+
+impl fmt::Display for ClipboardError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self {
+            ClipboardError::NullPointer => "null pointer passed across FFI",
+            ClipboardError::InvalidUtf8 => "string argument is not valid UTF-8",
+            ClipboardError::DuplicateName => "a register with that name already exists",
+            ClipboardError::NotFound => "no register with that name",
+            ClipboardError::ContentTooLarge => "content exceeds the configured size limit",
+            ClipboardError::EmptyHistory => "register has no history to undo",
+            ClipboardError::ShortcutInUse => "shortcut is already assigned to another register",
+            ClipboardError::Io => "failed to read or write a file",
+            ClipboardError::InvalidFormat => "input could not be parsed",
+            ClipboardError::Internal => "internal error",
+            ClipboardError::InvalidShortcut => "shortcut is not a valid key combination",
+            ClipboardError::InvalidArgument => "invalid argument",
+            ClipboardError::InvalidName => "register name is empty or only whitespace",
+            ClipboardError::ActiveProfile => "the active profile cannot be deleted",
+            ClipboardError::SaveFailed => "change was applied but could not be saved",
+            ClipboardError::Locked => "register is locked",
+            ClipboardError::RegisterLimit => "register limit reached and no register can be evicted",
+            ClipboardError::Busy => "timed out waiting for another call to finish",
+            ClipboardError::Conflict => "register changed since it was read",
+            ClipboardError::Destroyed => "manager has been destroyed",
+        };
+        write!(f, "{}", message)
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct ClipboardState {
+    #[serde(serialize_with = "serialize_sorted")]
+    registers: HashMap<String, ClipboardRegister>,
+    // Display order of register names; always holds exactly the keys of
+    // `registers`
+    #[serde(default)]
+    order: Vec<String>,
+    // Upper bound on a single register's content, in UTF-8 bytes
+    #[serde(default)]
+    max_content_bytes: Option<usize>,
+    #[serde(default = "default_history_limit")]
+    history_limit: usize,
+    // Store content with CRLF turned into LF and trailing whitespace removed
+    // from every line; see normalize_content. Off by default.
+    #[serde(default)]
+    normalize_on_store: bool,
+    // Cap on the number of registers in the active profile; see make_room
+    #[serde(default)]
+    max_registers: Option<usize>,
+    // `registers` and `order` above belong to this profile; the others are
+    // parked in `profiles` until switched to. Flat configs written before
+    // profiles existed load as the "default" profile.
+    #[serde(default = "default_profile_name")]
+    active_profile: String,
+    #[serde(default)]
+    profiles: HashMap<String, Profile>,
+    // Removed registers, oldest first, bounded by TRASH_LIMIT
+    #[serde(default)]
+    trash: Vec<(String, ClipboardRegister)>,
+    // Write minified JSON and leave default-valued register fields out.
+    // Smaller files, but much harder to read or edit by hand.
+    #[serde(default)]
+    compact: bool,
+    // Gzip the config on save; see COMPRESSED_MAGIC
+    #[serde(default)]
+    compress: bool,
+    // How many previous configs to keep as config.json.1 (newest) through
+    // config.json.N; 0 keeps none
+    #[serde(default)]
+    backup_count: usize,
+    // Where this state is persisted; None means resolve the default location
+    #[serde(skip)]
+    config_path: Option<PathBuf>,
+    // Set when the config is encrypted at rest
+    #[serde(skip)]
+    encryption: Option<EncryptionKey>,
+    // When set, mutations only mark the state dirty and the manager's
+    // background flusher writes it out
+    #[serde(skip)]
+    defer_saves: bool,
+    #[serde(skip)]
+    dirty: bool,
+    // Never read from or written to disk
+    #[serde(skip)]
+    in_memory: bool,
+    // Fingerprint of the config file as this state last wrote or read it,
+    // so the auto-reloader can ignore our own saves
+    #[serde(skip)]
+    disk_hash: Option<u64>,
+    // Where timestamps come from; SystemClock unless replaced with set_clock,
+    // e.g. by a test
+    #[serde(skip, default = "system_clock")]
+    clock: Arc<dyn Clock>,
+    // Set by load when there was no config file yet; stays set for the life
+    // of the state even once registers are added and removed
+    #[serde(skip)]
+    first_run: bool,
+    // Registers dropped by make_room, purge_expired or remove_where since the
+    // manager last looked, so it can notify about them
+    #[serde(skip)]
+    evicted: Vec<String>,
+    // How the last write of the config went: SaveFailed if it failed, Io if
+    // it was saved but backing up the previous config failed. Kept for
+    // health_check, since background and deferred saves have no caller to
+    // report to.
+    #[serde(skip)]
+    save_error: Option<ClipboardError>,
+}
+
+// A register set that is not currently active
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Profile {
+    #[serde(serialize_with = "serialize_sorted")]
     registers: HashMap<String, ClipboardRegister>,
+    #[serde(default)]
+    order: Vec<String>,
+}
+
+// Writes register maps sorted by name so saving unchanged state produces
+// byte-identical files that diff cleanly
+fn serialize_sorted<S: serde::Serializer>(
+    registers: &HashMap<String, ClipboardRegister>,
+    serializer: S
+) -> Result<S::Ok, S::Error> {
+    let sorted: BTreeMap<&String, &ClipboardRegister> = registers.iter().collect();
+    sorted.serialize(serializer)
+}
+
+pub const DEFAULT_HISTORY_LIMIT: usize = 10;
+pub const DEFAULT_PROFILE: &str = "default";
+pub const TRASH_LIMIT: usize = 20;
+// Approximate compact JSON size of a register with every variable-length
+// field empty, and of the top-level settings; see estimated_file_size
+pub const REGISTER_JSON_OVERHEAD: usize = 200;
+pub const STATE_JSON_OVERHEAD: usize = 250;
+// Extra bytes of indentation and newlines per register when the config is
+// not saved compact
+pub const REGISTER_PRETTY_OVERHEAD: usize = 170;
+// Fuzzy search only looks at this many leading characters of content so
+// large registers stay cheap to score
+pub const FUZZY_CONTENT_CHARS: usize = 256;
+
+fn default_profile_name() -> String {
+    DEFAULT_PROFILE.to_string()
+}
+
+fn default_history_limit() -> usize {
+    DEFAULT_HISTORY_LIMIT
+}
+
+// Makes `order` match the keys of `registers`: drops stale names and appends
+// any missing ones in sorted order
+fn sync_order(registers: &HashMap<String, ClipboardRegister>, order: &mut Vec<String>) {
+    order.retain(|name| registers.contains_key(name));
+    
+    let mut seen = HashSet::new();
+    order.retain(|name| seen.insert(name.clone()));
+    
+    let mut missing: Vec<String> = registers.keys()
+        .filter(|name| !seen.contains(*name))
+        .cloned()
+        .collect();
+    missing.sort();
+    order.extend(missing);
+}
+
+// Moves registers saved under non-NFC names (by older versions) to their
+// NFC name. One whose NFC name is already taken keeps its old name rather
+// than being merged.
+fn normalize_keys(registers: &mut HashMap<String, ClipboardRegister>, order: &mut [String]) {
+    let stale: Vec<String> = registers.keys().filter(|name| !is_nfc(name)).cloned().collect();
+    for old in stale {
+        let new = name_key(&old).into_owned();
+        if registers.contains_key(&new) {
+            continue;
+        }
+        if let Some(register) = registers.remove(&old) {
+            registers.insert(new.clone(), register);
+        }
+        for name in order.iter_mut().filter(|name| **name == old) {
+            *name = new.clone();
+        }
+    }
+}
+
+// Register names are stored trimmed and in NFC and must not be empty or
+// whitespace
+fn clean_name(name: &str) -> Result<String, ClipboardError> {
+    let trimmed = name.trim();
+    if trimmed.is_empty() {
+        return Err(ClipboardError::InvalidName);
+    }
+    Ok(trimmed.nfc().collect())
+}
+
+// The form a name is stored under, so a composed "é" and "e" followed by a
+// combining acute find the same register. Borrows when already NFC, which
+// is the case for all ASCII.
+fn name_key(name: &str) -> Cow<'_, str> {
+    if is_nfc(name) {
+        Cow::Borrowed(name)
+    } else {
+        Cow::Owned(name.nfc().collect())
+    }
+}
+
+// CRLF and lone CR become LF and trailing whitespace is stripped from each
+// line. Line breaks themselves, including a final one, are kept.
+fn normalize_content(content: &str) -> String {
+    content.replace("\r\n", "\n")
+        .replace('\r', "\n")
+        .split('\n')
+        .map(str::trim_end)
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+// Drops the oldest entries so at most `limit` remain
+fn trim_history(history: &mut Vec<String>, limit: usize) {
+    if history.len() > limit {
+        let excess = history.len() - limit;
+        history.drain(..excess);
+    }
+}
+
+// Overrides the default config location when no explicit path is given
+pub const CONFIG_PATH_ENV: &str = "CLIPBOARD_MANAGER_CONFIG";
+
+fn no_config_path_error() -> String {
+    format!("No home directory to keep the config in; set {} or pass a config path", CONFIG_PATH_ENV)
 }
 
 impl ClipboardState {
     pub fn new() -> Self {
         ClipboardState {
             registers: HashMap::new(),
+            order: Vec::new(),
+            max_content_bytes: None,
+            history_limit: DEFAULT_HISTORY_LIMIT,
+            normalize_on_store: false,
+            max_registers: None,
+            active_profile: default_profile_name(),
+            profiles: HashMap::new(),
+            trash: Vec::new(),
+            compact: false,
+            compress: false,
+            backup_count: 0,
+            config_path: None,
+            encryption: None,
+            defer_saves: false,
+            dirty: false,
+            in_memory: false,
+            disk_hash: None,
+            clock: system_clock(),
+            first_run: false,
+            evicted: Vec::new(),
+            save_error: None,
         }
     }
     
-    pub fn add_register(&mut self, name: String, shortcut: String) -> bool {
+    pub fn with_config_path(path: PathBuf) -> Self {
+        let mut state = ClipboardState::new();
+        state.config_path = Some(path);
+        state
+    }
+    
+    // An empty state whose saves are no-ops
+    pub fn in_memory() -> Self {
+        let mut state = ClipboardState::new();
+        state.in_memory = true;
+        state
+    }
+    
+    pub fn add_register(&mut self, name: String, shortcut: String) -> Result<(), ClipboardError> {
+        let name = clean_name(&name)?;
         if self.registers.contains_key(&name) {
-            return false;
+            return Err(ClipboardError::DuplicateName);
         }
+        self.make_room(None)?;
         
-        self.registers.insert(name, ClipboardRegister {
-            content: String::new(),
-            shortcut,
-        });
+        self.order.push(name.clone());
+        self.registers.insert(name, ClipboardRegister::new_at(String::new(), shortcut, self.now()));
         
-        self.save_to_disk().unwrap_or_else(|e| {
-            eprintln!("Failed to save config: {}", e);
-        });
+        self.persist()?;
         
-        true
+        Ok(())
     }
     
-    pub fn update_register_content(&mut self, name: &str, content: String) -> bool {
-        if let Some(register) = self.registers.get_mut(name) {
-            register.content = content;
-            
-            self.save_to_disk().unwrap_or_else(|e| {
-                eprintln!("Failed to save config: {}", e);
-            });
+    // Adds (name, shortcut, content) entries with a single save. Content is
+    // normalized as by update_register_content. Entries whose name is blank
+    // or already taken, or whose content is then over the size limit, are
+    // skipped. Returns how many were added.
+    pub fn add_registers(&mut self, entries: Vec<(String, String, String)>) -> Result<usize, ClipboardError> {
+        let mut added = 0;
+        for (name, shortcut, content) in entries {
+            let name = match clean_name(&name) {
+                Ok(name) => name,
+                Err(_) => continue
+            };
+            if self.registers.contains_key(&name) {
+                continue;
+            }
+            let content = if self.normalize_on_store {
+                normalize_content(&content)
+            } else {
+                content
+            };
+            if self.max_content_bytes.is_some_and(|max| content.len() > max) {
+                continue;
+            }
+            if self.make_room(None).is_err() {
+                break;
+            }
             
-            return true;
+            self.order.push(name.clone());
+            self.registers.insert(name, ClipboardRegister::new_at(content, shortcut, self.now()));
+            added += 1;
+        }
+        
+        if added > 0 {
+            self.persist()?;
+        }
+        
+        Ok(added)
+    }
+    
+    // Like add_register, but also refuses a shortcut owned by another register
+    pub fn add_register_checked(&mut self, name: String, shortcut: String) -> Result<(), ClipboardError> {
+        let name = clean_name(&name)?;
+        if self.registers.contains_key(&name) {
+            return Err(ClipboardError::DuplicateName);
         }
-        false
+        
+        if self.shortcut_in_use(&shortcut).is_some() {
+            return Err(ClipboardError::ShortcutInUse);
+        }
+        
+        self.add_register(name, shortcut)
     }
     
-    pub fn get_register_content(&self, name: &str) -> Option<String> {
-        self.registers.get(name).map(|r| r.content.clone())
+    // Validating variants of add_register/update_shortcut: the shortcut must
+    // parse (or be empty) and is stored in normalized form, which is returned
+    pub fn add_register_validated(&mut self, name: String, shortcut: &str) -> Result<String, ClipboardError> {
+        let normalized = normalize_shortcut(shortcut)?;
+        self.add_register(name, normalized.clone())?;
+        Ok(normalized)
     }
     
-    pub fn remove_register(&mut self, name: &str) -> bool {
-        if self.registers.remove(name).is_some() {
-            self.save_to_disk().unwrap_or_else(|e| {
-                eprintln!("Failed to save config: {}", e);
-            });
-            return true;
-        }
-        false
+    pub fn update_shortcut_validated(&mut self, name: &str, shortcut: &str) -> Result<String, ClipboardError> {
+        let normalized = normalize_shortcut(shortcut)?;
+        self.update_shortcut(name, normalized.clone())?;
+        Ok(normalized)
     }
     
-    pub fn update_shortcut(&mut self, name: &str, shortcut: String) -> bool {
-        if let Some(register) = self.registers.get_mut(name) {
-            register.shortcut = shortcut;
-            
-            self.save_to_disk().unwrap_or_else(|e| {
-                eprintln!("Failed to save config: {}", e);
-            });
-            
-            return true;
-        }
-        false
+    // Returns the name of the register bound to `shortcut`. Empty shortcuts
+    // are never considered in use.
+    pub fn shortcut_in_use(&self, shortcut: &str) -> Option<String> {
+        self.find_by_shortcut(shortcut).map(|(name, _)| name)
     }
     
-    pub fn get_all_registers(&self) -> Vec<(String, ClipboardRegister)> {
+    pub fn get_shortcut(&self, name: &str) -> Option<String> {
+        let name = &*name_key(name);
+        self.registers.get(name).map(|r| r.shortcut.clone())
+    }
+    
+    // name -> shortcut for every register with a shortcut, hidden ones
+    // included, for registering hotkeys without touching any content
+    pub fn shortcut_map(&self) -> HashMap<String, String> {
         self.registers.iter()
-            .map(|(name, register)| (name.clone(), register.clone()))
+            .filter(|(_, register)| !register.shortcut.is_empty())
+            .map(|(name, register)| (name.clone(), register.shortcut.clone()))
             .collect()
     }
     
-    fn get_config_path() -> PathBuf {
-        let mut path = dirs::home_dir().unwrap_or_default();
-        path.push(".clipboard_manager_config.json");
-        path
+    // Compares normalized shortcuts, so "cmd+c" finds a register bound to
+    // "Cmd+C". An empty shortcut matches nothing.
+    pub fn find_by_shortcut(&self, shortcut: &str) -> Option<(String, ClipboardRegister)> {
+        if shortcut.is_empty() {
+            return None;
+        }
+        
+        let wanted = shortcut_key(shortcut);
+        self.order.iter()
+            .filter_map(|name| self.registers.get(name).map(|r| (name, r)))
+            .find(|(_, register)| !register.shortcut.is_empty() && shortcut_key(&register.shortcut) == wanted)
+            .map(|(name, register)| (name.clone(), register.clone()))
     }
     
-    pub fn load_from_disk() -> Result<Self, String> {
-        let path = Self::get_config_path();
+    // Creates the register with this content and shortcut, or overwrites both
+    // if it already exists, with a single save either way
+    pub fn set_register(&mut self, name: &str, content: String, shortcut: String) -> Result<SetOutcome, ClipboardError> {
+        let name = clean_name(name)?;
+        let content = if self.normalize_on_store {
+            normalize_content(&content)
+        } else {
+            content
+        };
+        if self.max_content_bytes.is_some_and(|max| content.len() > max) {
+            return Err(ClipboardError::ContentTooLarge);
+        }
         
-        match fs::read_to_string(&path) {
-            Ok(contents) => {
-                match serde_json::from_str::<ClipboardState>(&contents) {
-                    Ok(state) => Ok(state),
-                    Err(e) => Err(format!("Failed to parse config: {}", e))
-                }
-            },
-            Err(_) => Ok(ClipboardState::new())
+        if !self.registers.contains_key(&name) {
+            self.make_room(None)?;
+            self.order.push(name.clone());
+            self.registers.insert(name, ClipboardRegister::new_at(content, shortcut, self.now()));
+            
+            self.persist()?;
+            return Ok(SetOutcome::Created);
+        }
+        
+        let history_limit = self.history_limit;
+        let now = self.now();
+        let register = self.unlocked_mut(&name)?;
+        let mut changed = false;
+        if register.is_binary() || register.content != content {
+            register.set_text_content(content, history_limit, now);
+            changed = true;
+        }
+        if register.shortcut != shortcut {
+            register.shortcut = shortcut;
+            register.updated_at = Some(now);
+            changed = true;
+        }
+        
+        if changed {
+            self.persist()?;
         }
+        Ok(SetOutcome::Updated)
     }
     
-    pub fn save_to_disk(&self) -> Result<(), String> {
-        let path = Self::get_config_path();
-        
-        match serde_json::to_string_pretty(self) {
-            Ok(json) => {
-                if let Err(e) = fs::write(&path, json) {
-                    return Err(format!("Failed to write config: {}", e));
-                }
-                Ok(())
-            },
-            Err(e) => Err(format!("Failed to serialize config: {}", e))
+    // Compare-and-swap: only updates if the content still hashes to
+    // `expected_hash` (see ClipboardRegister::content_hash), otherwise fails
+    // with Conflict and changes nothing
+    pub fn update_register_content_cas(&mut self, name: &str, expected_hash: &str, new_content: String) -> Result<bool, ClipboardError> {
+        let name = &*name_key(name);
+        let register = self.registers.get(name).ok_or(ClipboardError::NotFound)?;
+        if !register.content_hash().eq_ignore_ascii_case(expected_hash) {
+            return Err(ClipboardError::Conflict);
         }
+        
+        self.update_register_content(name, new_content)
     }
-}
-
-// This object will be shared with Swift via FFI
-pub struct ClipboardManager {
-    state: Arc<Mutex<ClipboardState>>,
-}
-
-impl ClipboardManager {
-    pub fn new() -> Self {
-        let state = match ClipboardState::load_from_disk() {
-            Ok(state) => state,
-            Err(e) => {
-                eprintln!("Failed to load config: {}", e);
-                ClipboardState::new()
-            }
+    
+    // Normalizes the content first when normalize_on_store is set
+    pub fn update_register_content(&mut self, name: &str, content: String) -> Result<bool, ClipboardError> {
+        let content = if self.normalize_on_store {
+            normalize_content(&content)
+        } else {
+            content
         };
+        self.update_register_content_raw(name, content)
+    }
+    
+    // Stores the content exactly as given. Returns false, without saving, if
+    // the register already held exactly this text.
+    pub fn update_register_content_raw(&mut self, name: &str, content: String) -> Result<bool, ClipboardError> {
+        let name = &*name_key(name);
+        if let Some(max) = self.max_content_bytes {
+            if content.len() > max {
+                return Err(ClipboardError::ContentTooLarge);
+            }
+        }
         
-        ClipboardManager {
-            state: Arc::new(Mutex::new(state)),
+        let history_limit = self.history_limit;
+        let now = self.now();
+        let register = self.unlocked_mut(name)?;
+        // Clipboard sync rewrites the same text constantly; skip the save
+        if !register.is_binary() && register.content == content {
+            return Ok(false);
         }
+        register.set_text_content(content, history_limit, now);
+        
+        self.persist()?;
+        
+        Ok(true)
     }
     
-    // Core functions that will be exposed to Swift
-    pub fn add_register(&self, name: &str, shortcut: &str) -> bool {
-        let mut state = self.state.lock().unwrap();
-        state.add_register(name.to_string(), shortcut.to_string())
+    // Same as update_register_content but hands back the content it replaced,
+    // so callers don't need a separate (racy) read first
+    pub fn replace_register_content(&mut self, name: &str, content: String) -> Result<String, ClipboardError> {
+        let previous = self.get_register_content(name).ok_or(ClipboardError::NotFound)?;
+        self.update_register_content(name, content)?;
+        Ok(previous)
     }
     
-    pub fn update_register_content(&self, name: &str, content: &str) -> bool {
-        let mut state = self.state.lock().unwrap();
-        state.update_register_content(name, content.to_string())
+    pub fn get_register_content(&self, name: &str) -> Option<String> {
+        let name = &*name_key(name);
+        self.registers.get(name).map(|r| r.content.clone())
     }
     
-    pub fn get_register_content(&self, name: &str) -> Option<String> {
-        let state = self.state.lock().unwrap();
-        state.get_register_content(name)
+    pub fn get_register_hash(&self, name: &str) -> Option<String> {
+        let name = &*name_key(name);
+        self.registers.get(name).map(|r| r.content_hash())
     }
     
-    pub fn remove_register(&self, name: &str) -> bool {
-        let mut state = self.state.lock().unwrap();
-        state.remove_register(name)
+    // Content with `{key}` placeholders filled in from `vars`; see
+    // expand_template. Binary registers are returned unchanged.
+    pub fn get_register_content_with_vars(&self, name: &str, vars: &HashMap<String, String>) -> Option<String> {
+        let name = &*name_key(name);
+        let register = self.registers.get(name)?;
+        if register.is_binary() {
+            return Some(register.content.clone());
+        }
+        Some(expand_template(&register.content, vars))
     }
     
-    pub fn update_shortcut(&self, name: &str, shortcut: &str) -> bool {
-        let mut state = self.state.lock().unwrap();
-        state.update_shortcut(name, shortcut.to_string())
+    // Line diff from the stored content to `other`
+    pub fn diff_content(&self, name: &str, other: &str) -> Option<Vec<DiffLine>> {
+        let name = &*name_key(name);
+        let register = self.registers.get(name)?;
+        Some(diff_lines(&register.content, other))
     }
     
-    pub fn get_all_registers(&self) -> String {
-        let state = self.state.lock().unwrap();
-        let registers = state.get_all_registers();
-        
-        match serde_json::to_string(&registers) {
-            Ok(json) => json,
-            Err(_) => "[]".to_string()
+    // Content with `$VAR` and `${VAR}` replaced from the process environment;
+    // see expand_env. Binary registers are returned unchanged.
+    pub fn get_register_content_expanded(&self, name: &str) -> Option<String> {
+        let name = &*name_key(name);
+        let register = self.registers.get(name)?;
+        if register.is_binary() {
+            return Some(register.content.clone());
         }
+        Some(expand_env(&register.content))
     }
-}
-
-// C-compatible FFI functions to expose to Swift
-use std::os::raw::{c_char, c_int};
-use std::ffi::{CStr, CString};
-
-#[no_mangle]
-pub extern "C" fn clipboard_manager_new() -> *mut ClipboardManager {
-    Box::into_raw(Box::new(ClipboardManager::new()))
-}
-
-#[no_mangle]
-pub extern "C" fn clipboard_manager_destroy(manager: *mut ClipboardManager) {
-    if !manager.is_null() {
-        unsafe { Box::from_raw(manager); }
-    }
-}
-
-#[no_mangle]
-pub extern "C" fn clipboard_manager_add_register(
-    manager: *mut ClipboardManager,
-    name: *const c_char,
-    shortcut: *const c_char
-) -> c_int {
-    let manager = unsafe {
-        assert!(!manager.is_null());
-        &*manager
-    };
-    
-    let name = unsafe {
-        assert!(!name.is_null());
-        CStr::from_ptr(name).to_str().unwrap_or("")
-    };
     
-    let shortcut = unsafe {
-        assert!(!shortcut.is_null());
-        CStr::from_ptr(shortcut).to_str().unwrap_or("")
-    };
+    // Content with `{{date}}`-style tokens filled in from the local time; see
+    // render_time_tokens. Binary registers, and content with an invalid
+    // `{{date:...}}` format, are returned unchanged.
+    pub fn get_register_content_rendered(&self, name: &str) -> Option<String> {
+        let name = &*name_key(name);
+        let register = self.registers.get(name)?;
+        if register.is_binary() {
+            return Some(register.content.clone());
+        }
+        let now = DateTime::from_timestamp(self.now() as i64, 0)
+            .map_or_else(Local::now, |utc| utc.with_timezone(&Local));
+        let rendered = render_time_tokens(&register.content, &now);
+        Some(rendered.unwrap_or_else(|| register.content.clone()))
+    }
     
-    if manager.add_register(name, shortcut) { 1 } else { 0 }
-}
-
-#[no_mangle]
-pub extern "C" fn clipboard_manager_update_register_content(
-    manager: *mut ClipboardManager,
-    name: *const c_char,
-    content: *const c_char
-) -> c_int {
-    let manager = unsafe {
-        assert!(!manager.is_null());
-        &*manager
-    };
+    // Empties the content but keeps the register and its shortcut; the old
+    // content goes to the history so it can be undone
+    pub fn clear_register_content(&mut self, name: &str) -> Result<bool, ClipboardError> {
+        self.update_register_content(name, String::new())
+    }
     
-    let name = unsafe {
-        assert!(!name.is_null());
-        CStr::from_ptr(name).to_str().unwrap_or("")
-    };
+    // Locked registers keep their content
+    pub fn clear_all_contents(&mut self) -> Result<(), ClipboardError> {
+        let history_limit = self.history_limit;
+        let now = self.now();
+        for register in self.registers.values_mut() {
+            if !register.content.is_empty() && !register.locked {
+                register.set_text_content(String::new(), history_limit, now);
+            }
+        }
+        
+        self.persist()
+    }
     
-    let content = unsafe {
-        assert!(!content.is_null());
-        CStr::from_ptr(content).to_str().unwrap_or("")
-    };
+    // Appends to a text register's content, inserting `separator` first only
+    // when the existing content is non-empty
+    pub fn append_register_content(&mut self, name: &str, content: &str, separator: Option<&str>) -> Result<bool, ClipboardError> {
+        let name = &*name_key(name);
+        let register = self.registers.get(name).ok_or(ClipboardError::NotFound)?;
+        if register.is_binary() {
+            return Err(ClipboardError::InvalidFormat);
+        }
+        
+        let mut combined = register.content.clone();
+        if let Some(separator) = separator {
+            if !combined.is_empty() {
+                combined.push_str(separator);
+            }
+        }
+        combined.push_str(content);
+        
+        self.update_register_content(name, combined)
+    }
     
-    if manager.update_register_content(name, content) { 1 } else { 0 }
-}
-
-#[no_mangle]
-pub extern "C" fn clipboard_manager_get_register_content(
-    manager: *mut ClipboardManager,
-    name: *const c_char
-) -> *mut c_char {
-    let manager = unsafe {
-        assert!(!manager.is_null());
-        &*manager
-    };
+    pub fn get_register(&self, name: &str) -> Option<ClipboardRegister> {
+        let name = &*name_key(name);
+        self.registers.get(name).cloned()
+    }
     
-    let name = unsafe {
-        assert!(!name.is_null());
-        CStr::from_ptr(name).to_str().unwrap_or("")
-    };
+    // Returns the register, first adding it empty with `default_shortcut` if
+    // it does not exist. Only saves when it adds one.
+    pub fn get_or_create(&mut self, name: &str, default_shortcut: &str) -> Result<ClipboardRegister, ClipboardError> {
+        let name = clean_name(name)?;
+        if let Some(register) = self.registers.get(&name) {
+            return Ok(register.clone());
+        }
+        
+        self.add_register(name.clone(), default_shortcut.to_string())?;
+        Ok(self.registers[&name].clone())
+    }
     
-    match manager.get_register_content(name) {
-        Some(content) => {
-            match CString::new(content) {
-                Ok(c_str) => c_str.into_raw(),
-                Err(_) => std::ptr::null_mut()
+    // Stores `data` base64-encoded in `content` and tags it with `mime`.
+    // text/plain is refused, as the base64 would then be read back as the
+    // text itself; use update_register_content for text.
+    pub fn update_register_binary(&mut self, name: &str, mime: &str, data: &[u8]) -> Result<(), ClipboardError> {
+        let name = &*name_key(name);
+        if is_plain_text(mime) {
+            return Err(ClipboardError::InvalidArgument);
+        }
+        let encoded = BASE64.encode(data);
+        if let Some(max) = self.max_content_bytes {
+            if encoded.len() > max {
+                return Err(ClipboardError::ContentTooLarge);
             }
-        },
-        None => std::ptr::null_mut()
+        }
+        
+        let now = self.now();
+        let register = self.unlocked_mut(name)?;
+        register.content = encoded;
+        register.content_type = mime.to_string();
+        register.updated_at = Some(now);
+        
+        self.persist()?;
+        
+        Ok(())
     }
-}
-
-#[no_mangle]
-pub extern "C" fn clipboard_manager_remove_register(
-    manager: *mut ClipboardManager,
-    name: *const c_char
-) -> c_int {
-    let manager = unsafe {
-        assert!(!manager.is_null());
-        &*manager
-    };
     
-    let name = unsafe {
-        assert!(!name.is_null());
-        CStr::from_ptr(name).to_str().unwrap_or("")
-    };
+    // Raw bytes of a register: decoded for binary types, UTF-8 for text
+    pub fn get_register_binary(&self, name: &str) -> Option<Vec<u8>> {
+        let name = &*name_key(name);
+        let register = self.registers.get(name)?;
+        if register.is_binary() {
+            BASE64.decode(&register.content).ok()
+        } else {
+            Some(register.content.clone().into_bytes())
+        }
+    }
+    
+    // Computed from the content on every call; counting is linear and far
+    // cheaper than the FFI round trip, so nothing is cached
+    pub fn register_stats(&self, name: &str) -> Option<RegisterStats> {
+        let name = &*name_key(name);
+        let register = self.registers.get(name)?;
+        if register.is_binary() {
+            let bytes = self.get_register_binary(name).map_or(0, |data| data.len());
+            return Some(RegisterStats { bytes, ..RegisterStats::default() });
+        }
+        
+        let content = &register.content;
+        Some(RegisterStats {
+            chars: content.chars().count(),
+            bytes: content.len(),
+            words: content.split_whitespace().count(),
+            lines: content.lines().count(),
+        })
+    }
+    
+    pub fn get_register_history(&self, name: &str) -> Option<Vec<String>> {
+        let name = &*name_key(name);
+        self.registers.get(name).map(|r| r.history.clone())
+    }
+    
+    // Restores the most recent history entry as the current content
+    pub fn undo_register_content(&mut self, name: &str) -> Result<(), ClipboardError> {
+        let name = &*name_key(name);
+        let now = self.now();
+        let register = self.unlocked_mut(name)?;
+        let previous = register.history.pop().ok_or(ClipboardError::EmptyHistory)?;
+        register.content = previous;
+        register.content_type = default_content_type();
+        register.updated_at = Some(now);
+        
+        self.persist()?;
+        
+        Ok(())
+    }
+    
+    // The register goes to the trash, from which it can be restored
+    pub fn remove_register(&mut self, name: &str) -> Result<(), ClipboardError> {
+        let name = &*name_key(name);
+        self.unlocked_mut(name)?;
+        if let Some(register) = self.registers.remove(name) {
+            self.push_trash(name.to_string(), register);
+        }
+        self.order.retain(|n| n != name);
+        
+        self.persist()?;
+        
+        Ok(())
+    }
+    
+    // Names of registers with byte-identical content (and content type),
+    // grouped, in display order. Only groups of two or more are returned,
+    // and empty registers are never counted as duplicates.
+    pub fn find_duplicates(&self) -> Vec<Vec<String>> {
+        let mut groups: Vec<Vec<String>> = Vec::new();
+        let mut seen: HashMap<(&str, &str), usize> = HashMap::new();
+        for name in &self.order {
+            let register = match self.registers.get(name) {
+                Some(register) if !register.content.is_empty() => register,
+                _ => continue
+            };
+            let key = (register.content_type.as_str(), register.content.as_str());
+            match seen.get(&key) {
+                Some(&index) => groups[index].push(name.clone()),
+                None => {
+                    seen.insert(key, groups.len());
+                    groups.push(vec![name.clone()]);
+                }
+            }
+        }
+        groups.retain(|group| group.len() > 1);
+        groups
+    }
+    
+    // Removes all but one register from each find_duplicates group, choosing
+    // the survivor by `keep`, and returns how many went. Removed registers go
+    // to the trash. Locked registers are never removed, so a group holding
+    // one may keep more than one register.
+    pub fn dedupe(&mut self, keep: DedupeKeep) -> Result<usize, ClipboardError> {
+        let mut doomed: HashSet<String> = HashSet::new();
+        for group in self.find_duplicates() {
+            let registers = &self.registers;
+            let keeper = match keep {
+                DedupeKeep::First => &group[0],
+                DedupeKeep::Pinned => group.iter().find(|name| registers[*name].pinned).unwrap_or(&group[0]),
+                // Reversed so the earliest wins a tie
+                DedupeKeep::MostRecent => group.iter().rev().max_by_key(|name| registers[*name].updated_at).unwrap_or(&group[0])
+            };
+            doomed.extend(group.iter().filter(|name| *name != keeper).cloned());
+        }
+        
+        self.remove_where(|name, _| doomed.contains(name))
+    }
+    
+    // Removes every unlocked register in the active profile for which
+    // `pred(name, register)` holds, moving them to the trash, with a single
+    // save. Returns how many were removed.
+    pub fn remove_where<F: Fn(&str, &ClipboardRegister) -> bool>(&mut self, pred: F) -> Result<usize, ClipboardError> {
+        let doomed: Vec<String> = self.order.iter()
+            .filter(|name| self.registers.get(*name).is_some_and(|r| !r.locked && pred(name, r)))
+            .cloned()
+            .collect();
+        if doomed.is_empty() {
+            return Ok(0);
+        }
+        
+        for name in &doomed {
+            if let Some(register) = self.registers.remove(name) {
+                self.push_trash(name.clone(), register);
+            }
+        }
+        self.order.retain(|name| !doomed.contains(name));
+        let count = doomed.len();
+        self.evicted.extend(doomed);
+        
+        self.persist()?;
+        Ok(count)
+    }
+    
+    fn push_trash(&mut self, name: String, register: ClipboardRegister) {
+        self.trash.push((name, register));
+        if self.trash.len() > TRASH_LIMIT {
+            let excess = self.trash.len() - TRASH_LIMIT;
+            self.trash.drain(..excess);
+        }
+    }
+    
+    // Makes the register expire `seconds` from now; None removes the expiry
+    pub fn set_register_ttl(&mut self, name: &str, seconds: Option<u64>) -> Result<(), ClipboardError> {
+        let name = &*name_key(name);
+        let now = self.now();
+        let register = self.registers.get_mut(name).ok_or(ClipboardError::NotFound)?;
+        register.expires_at = seconds.map(|seconds| now.saturating_add(seconds));
+        
+        self.persist()
+    }
+    
+    // Removes every expired register in the active profile, except locked
+    // ones, and returns how many went. Expired registers usually hold
+    // one-time secrets, so they skip the trash.
+    pub fn purge_expired(&mut self) -> Result<usize, ClipboardError> {
+        let now = self.now();
+        let expired: Vec<String> = self.order.iter()
+            .filter(|name| self.registers.get(*name).is_some_and(|r| !r.locked && r.is_expired(now)))
+            .cloned()
+            .collect();
+        if expired.is_empty() {
+            return Ok(0);
+        }
+        
+        for name in &expired {
+            self.registers.remove(name);
+        }
+        self.order.retain(|name| !expired.contains(name));
+        let count = expired.len();
+        self.evicted.extend(expired);
+        
+        self.persist()?;
+        Ok(count)
+    }
+    
+    // Oldest first
+    pub fn get_trash(&self) -> Vec<(String, ClipboardRegister)> {
+        self.trash.clone()
+    }
+    
+    // Restores the most recently removed register called `name` at the end
+    // of the order. Fails if a register with that name exists again.
+    pub fn restore_from_trash(&mut self, name: &str) -> Result<(), ClipboardError> {
+        let name = &*name_key(name);
+        let index = self.trash.iter().rposition(|(n, _)| n == name).ok_or(ClipboardError::NotFound)?;
+        if self.registers.contains_key(name) {
+            return Err(ClipboardError::DuplicateName);
+        }
+        self.make_room(None)?;
+        
+        let (name, register) = self.trash.remove(index);
+        self.order.push(name.clone());
+        self.registers.insert(name, register);
+        
+        self.persist()?;
+        
+        Ok(())
+    }
+    
+    pub fn empty_trash(&mut self) -> Result<(), ClipboardError> {
+        if self.trash.is_empty() {
+            return Ok(());
+        }
+        self.trash.clear();
+        
+        self.persist()
+    }
+    
+    pub fn rename_register(&mut self, old_name: &str, new_name: &str) -> Result<(), ClipboardError> {
+        let old_name = &*name_key(old_name);
+        if !self.registers.contains_key(old_name) {
+            return Err(ClipboardError::NotFound);
+        }
+        
+        let new_name = clean_name(new_name)?;
+        if old_name == new_name {
+            return Ok(());
+        }
+        
+        if self.registers.contains_key(&new_name) {
+            return Err(ClipboardError::DuplicateName);
+        }
+        
+        if let Some(register) = self.registers.remove(old_name) {
+            self.registers.insert(new_name.clone(), register);
+        }
+        if let Some(slot) = self.order.iter_mut().find(|n| n.as_str() == old_name) {
+            *slot = new_name;
+        }
+        
+        self.persist()?;
+        
+        Ok(())
+    }
+    
+    // Copies `src`'s content (and type and tags) into a new register with fresh
+    // timestamps and no history. The shortcut is left empty to avoid a clash.
+    pub fn duplicate_register(&mut self, src: &str, new_name: &str) -> Result<(), ClipboardError> {
+        let src = &*name_key(src);
+        let source = self.registers.get(src).ok_or(ClipboardError::NotFound)?;
+        let new_name = clean_name(new_name)?;
+        if self.registers.contains_key(&new_name) {
+            return Err(ClipboardError::DuplicateName);
+        }
+        
+        let mut copy = ClipboardRegister::new_at(source.content.clone(), String::new(), self.now());
+        copy.content_type = source.content_type.clone();
+        copy.tags = source.tags.clone();
+        self.make_room(Some(src))?;
+        
+        self.order.push(new_name.clone());
+        self.registers.insert(new_name, copy);
+        
+        self.persist()?;
+        
+        Ok(())
+    }
+    
+    // Appends `source`'s content to `target` (joined by `separator` when
+    // target is non-empty), then moves `source` to the trash. Saved once.
+    pub fn merge_registers(&mut self, target: &str, source: &str, separator: &str) -> Result<(), ClipboardError> {
+        let target = &*name_key(target);
+        let source = &*name_key(source);
+        if target == source {
+            return Err(ClipboardError::InvalidArgument);
+        }
+        
+        let target_register = self.registers.get(target).ok_or(ClipboardError::NotFound)?;
+        let source_register = self.registers.get(source).ok_or(ClipboardError::NotFound)?;
+        if target_register.locked || source_register.locked {
+            return Err(ClipboardError::Locked);
+        }
+        if target_register.is_binary() || source_register.is_binary() {
+            return Err(ClipboardError::InvalidFormat);
+        }
+        
+        let mut combined = target_register.content.clone();
+        if !combined.is_empty() {
+            combined.push_str(separator);
+        }
+        combined.push_str(&source_register.content);
+        
+        if let Some(max) = self.max_content_bytes {
+            if combined.len() > max {
+                return Err(ClipboardError::ContentTooLarge);
+            }
+        }
+        
+        let history_limit = self.history_limit;
+        let now = self.now();
+        if let Some(register) = self.registers.get_mut(target) {
+            register.set_text_content(combined, history_limit, now);
+        }
+        if let Some(removed) = self.registers.remove(source) {
+            self.push_trash(source.to_string(), removed);
+        }
+        self.order.retain(|n| n != source);
+        
+        self.persist()?;
+        
+        Ok(())
+    }
+    
+    // Exchanges the contents (and content types) of two registers, leaving
+    // shortcuts and everything else in place. Saved once.
+    pub fn swap_contents(&mut self, a: &str, b: &str) -> Result<(), ClipboardError> {
+        let a = &*name_key(a);
+        let b = &*name_key(b);
+        let first = self.registers.get(a).ok_or(ClipboardError::NotFound)?;
+        let second = self.registers.get(b).ok_or(ClipboardError::NotFound)?;
+        if a == b {
+            return Ok(());
+        }
+        if first.locked || second.locked {
+            return Err(ClipboardError::Locked);
+        }
+        
+        let swapped = [
+            (a, second.content.clone(), second.content_type.clone()),
+            (b, first.content.clone(), first.content_type.clone()),
+        ];
+        let now = self.now();
+        for (name, content, content_type) in swapped {
+            if let Some(register) = self.registers.get_mut(name) {
+                register.content = content;
+                register.content_type = content_type;
+                register.updated_at = Some(now);
+            }
+        }
+        
+        self.persist()?;
+        
+        Ok(())
+    }
+    
+    pub fn update_shortcut(&mut self, name: &str, shortcut: String) -> Result<(), ClipboardError> {
+        let name = &*name_key(name);
+        let now = self.now();
+        let register = self.unlocked_mut(name)?;
+        if register.shortcut == shortcut {
+            return Ok(());
+        }
+        register.shortcut = shortcut;
+        register.updated_at = Some(now);
+        
+        self.persist()?;
+        
+        Ok(())
+    }
+    
+    // Changes the content, the shortcut, or both with a single save; None
+    // leaves that field as it is. Either both changes apply or neither does.
+    pub fn update_register(&mut self, name: &str, content: Option<String>, shortcut: Option<String>) -> Result<(), ClipboardError> {
+        let name = &*name_key(name);
+        let content = match content {
+            Some(content) if self.normalize_on_store => Some(normalize_content(&content)),
+            content => content
+        };
+        if let (Some(content), Some(max)) = (&content, self.max_content_bytes) {
+            if content.len() > max {
+                return Err(ClipboardError::ContentTooLarge);
+            }
+        }
+        
+        let history_limit = self.history_limit;
+        let now = self.now();
+        let register = self.unlocked_mut(name)?;
+        let mut changed = false;
+        if let Some(content) = content {
+            if register.is_binary() || register.content != content {
+                register.set_text_content(content, history_limit, now);
+                changed = true;
+            }
+        }
+        if let Some(shortcut) = shortcut {
+            if register.shortcut != shortcut {
+                register.shortcut = shortcut;
+                register.updated_at = Some(now);
+                changed = true;
+            }
+        }
+        if !changed {
+            return Ok(());
+        }
+        
+        self.persist()
+    }
+    
+    pub fn update_shortcut_checked(&mut self, name: &str, shortcut: String) -> Result<(), ClipboardError> {
+        let name = &*name_key(name);
+        if !self.registers.contains_key(name) {
+            return Err(ClipboardError::NotFound);
+        }
+        
+        if let Some(owner) = self.shortcut_in_use(&shortcut) {
+            if owner != name {
+                return Err(ClipboardError::ShortcutInUse);
+            }
+        }
+        
+        self.update_shortcut(name, shortcut)
+    }
+    
+    pub fn set_normalize_on_store(&mut self, normalize: bool) -> Result<(), ClipboardError> {
+        self.normalize_on_store = normalize;
+        
+        self.persist()
+    }
+    
+    pub fn set_max_content_bytes(&mut self, max: Option<usize>) -> Result<(), ClipboardError> {
+        self.max_content_bytes = max;
+        
+        self.persist()
+    }
+    
+    // Takes effect on the next add; existing registers are not evicted until
+    // then. A max of zero would make every add fail and is rejected; use None
+    // for no cap.
+    pub fn set_max_registers(&mut self, max: Option<usize>) -> Result<(), ClipboardError> {
+        if max == Some(0) {
+            return Err(ClipboardError::InvalidArgument);
+        }
+        self.max_registers = max;
+        
+        self.persist()
+    }
+    
+    // Called before adding a register. While the profile is at or over
+    // max_registers, evicts the least recently used register: never-used
+    // registers first, then by ascending last_used_at, ties broken by older
+    // created_at and then by name. Pinned and locked registers, and `keep`,
+    // are never evicted. The victims are all picked before any is removed, so
+    // if there are too few the add fails with nothing evicted.
+    fn make_room(&mut self, keep: Option<&str>) -> Result<(), ClipboardError> {
+        let max = match self.max_registers {
+            Some(max) => max,
+            None => return Ok(())
+        };
+        let needed = (self.registers.len() + 1).saturating_sub(max);
+        if needed == 0 {
+            return Ok(());
+        }
+        
+        let mut candidates: Vec<(&String, &ClipboardRegister)> = self.registers.iter()
+            .filter(|(name, register)| !register.pinned && !register.locked && Some(name.as_str()) != keep)
+            .collect();
+        if candidates.len() < needed {
+            return Err(ClipboardError::RegisterLimit);
+        }
+        candidates.sort_by(|a, b| {
+            a.1.last_used_at.cmp(&b.1.last_used_at)
+                .then_with(|| a.1.created_at.cmp(&b.1.created_at))
+                .then_with(|| a.0.cmp(b.0))
+        });
+        let victims: Vec<String> = candidates.into_iter()
+            .take(needed)
+            .map(|(name, _)| name.clone())
+            .collect();
+        
+        for victim in &victims {
+            self.registers.remove(victim);
+        }
+        self.order.retain(|n| !victims.contains(n));
+        self.evicted.extend(victims);
+        Ok(())
+    }
+    
+    // Case-insensitive substring match over names and text content, sorted
+    // by name. An empty query matches every register. Like the other
+    // searches and listings below, hidden registers are left out unless
+    // `include_hidden` is set.
+    pub fn search(&self, query: &str, include_hidden: bool) -> Vec<(String, ClipboardRegister)> {
+        self.search_with_mode(query, SearchMode::Substring, include_hidden)
+    }
+    
+    // Case-insensitive in every mode; see SearchMode for what each matches
+    pub fn search_with_mode(&self, query: &str, mode: SearchMode, include_hidden: bool) -> Vec<(String, ClipboardRegister)> {
+        let query = query.to_lowercase();
+        let mut results: Vec<(String, ClipboardRegister)> = self.listed_registers(include_hidden)
+            .filter(|(name, register)| {
+                let name = name.to_lowercase();
+                match mode {
+                    SearchMode::Exact => name == query,
+                    SearchMode::Prefix => name.starts_with(&query),
+                    SearchMode::Substring => {
+                        name.contains(&query)
+                            || (!register.is_binary() && register.content.to_lowercase().contains(&query))
+                    }
+                }
+            })
+            .map(|(name, register)| (name.clone(), register.clone()))
+            .collect();
+        
+        results.sort_by(|a, b| a.0.cmp(&b.0));
+        results
+    }
+    
+    // Typo-tolerant search: each register scores the Levenshtein distance
+    // from `query` to its name, or to the closest word near the start of
+    // its text content (see FUZZY_CONTENT_CHARS) if that is closer. Keeps
+    // scores up to `max_distance`, best first, ties by name.
+    // Case-insensitive.
+    pub fn search_fuzzy(&self, query: &str, max_distance: usize, include_hidden: bool) -> Vec<(String, ClipboardRegister, usize)> {
+        let query: Vec<char> = query.to_lowercase().chars().collect();
+        let mut results: Vec<(String, ClipboardRegister, usize)> = self.listed_registers(include_hidden)
+            .filter_map(|(name, register)| {
+                let name_chars: Vec<char> = name.to_lowercase().chars().collect();
+                let mut best = bounded_levenshtein(&query, &name_chars, max_distance);
+                
+                if !register.is_binary() {
+                    let head: String = register.content.chars().take(FUZZY_CONTENT_CHARS).collect();
+                    for word in head.to_lowercase().split_whitespace() {
+                        let word: Vec<char> = word.chars().collect();
+                        let limit = best.map_or(max_distance, |d| d.saturating_sub(1));
+                        if let Some(d) = bounded_levenshtein(&query, &word, limit) {
+                            best = Some(d);
+                        }
+                    }
+                }
+                
+                best.map(|distance| (name.clone(), register.clone(), distance))
+            })
+            .collect();
+        
+        results.sort_by(|a, b| a.2.cmp(&b.2).then_with(|| a.0.cmp(&b.0)));
+        results
+    }
+    
+    pub fn add_tag(&mut self, name: &str, tag: &str) -> Result<(), ClipboardError> {
+        let name = &*name_key(name);
+        let register = self.registers.get_mut(name).ok_or(ClipboardError::NotFound)?;
+        if register.add_tag(tag) {
+            self.persist()?;
+        }
+        Ok(())
+    }
+    
+    pub fn remove_tag(&mut self, name: &str, tag: &str) -> Result<(), ClipboardError> {
+        let name = &*name_key(name);
+        let register = self.registers.get_mut(name).ok_or(ClipboardError::NotFound)?;
+        if register.remove_tag(tag) {
+            self.persist()?;
+        }
+        Ok(())
+    }
+    
+    // Registers carrying `tag`, sorted by name
+    pub fn get_registers_by_tag(&self, tag: &str, include_hidden: bool) -> Vec<(String, ClipboardRegister)> {
+        let mut results: Vec<(String, ClipboardRegister)> = self.listed_registers(include_hidden)
+            .filter(|(_, register)| register.tags.iter().any(|t| t == tag))
+            .map(|(name, register)| (name.clone(), register.clone()))
+            .collect();
+        
+        results.sort_by(|a, b| a.0.cmp(&b.0));
+        results
+    }
+    
+    // Registers matching every given predicate, sorted by name; None matches
+    // anything. Formats compare case-insensitively.
+    pub fn filter(&self, tag: Option<&str>, format: Option<&str>, include_hidden: bool) -> Vec<(String, ClipboardRegister)> {
+        let mut results: Vec<(String, ClipboardRegister)> = self.listed_registers(include_hidden)
+            .filter(|(_, register)| tag.is_none_or(|tag| register.tags.iter().any(|t| t == tag)))
+            .filter(|(_, register)| format.is_none_or(|format| {
+                register.format.as_deref().is_some_and(|f| f.eq_ignore_ascii_case(format))
+            }))
+            .map(|(name, register)| (name.clone(), register.clone()))
+            .collect();
+        
+        results.sort_by(|a, b| a.0.cmp(&b.0));
+        results
+    }
+    
+    pub fn set_history_limit(&mut self, limit: usize) -> Result<(), ClipboardError> {
+        self.history_limit = limit;
+        for register in self.registers.values_mut() {
+            trim_history(&mut register.history, limit);
+        }
+        
+        self.persist()
+    }
+    
+    pub fn len(&self) -> usize {
+        self.registers.len()
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.registers.is_empty()
+    }
+    
+    // Sum of the content lengths in bytes (base64 text for binary registers)
+    // of the active profile's registers
+    pub fn total_content_bytes(&self) -> usize {
+        self.registers.values().map(|r| r.content.len()).sum()
+    }
+    
+    // A quick estimate of the config file size in bytes, covering every
+    // profile and the trash, built from field lengths rather than by
+    // serializing. Ignores compression; encrypted files are somewhat larger.
+    pub fn estimated_file_size(&self) -> usize {
+        let per_register = if self.compact { 0 } else { REGISTER_PRETTY_OVERHEAD };
+        let parked = self.profiles.values().flat_map(|profile| profile.registers.iter());
+        let trashed = self.trash.iter().map(|(name, register)| (name, register));
+        let registers: usize = self.registers.iter().chain(parked).chain(trashed)
+            .map(|(name, register)| name.len() * 2 + 6 + per_register + register.estimated_json_size())
+            .sum();
+        let profiles: usize = self.profiles.keys().map(|name| name.len() + 30).sum();
+        STATE_JSON_OVERHEAD + registers + profiles
+    }
+    
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+    
+    // The current Unix second according to the state's clock
+    pub fn now(&self) -> u64 {
+        self.clock.now_secs()
+    }
+    
+    // Whether load found no config file. An existing but empty config is not
+    // a first run.
+    pub fn is_first_run(&self) -> bool {
+        self.first_run
+    }
+    
+    pub fn contains_register(&self, name: &str) -> bool {
+        let name = &*name_key(name);
+        self.registers.contains_key(name)
+    }
+    
+    // All names sorted by codepoint (locale-independent)
+    pub fn register_names(&self, include_hidden: bool) -> Vec<String> {
+        let mut names: Vec<String> = self.listed_registers(include_hidden)
+            .map(|(name, _)| name.clone())
+            .collect();
+        names.sort();
+        names
+    }
+    
+    // Registers in display order: pinned first, then the rest, each group
+    // keeping its relative `order`. Stable across calls; configs without an
+    // explicit order are listed by name. Hidden registers are left out.
+    pub fn get_all_registers(&self) -> Vec<(String, ClipboardRegister)> {
+        let mut registers = Vec::with_capacity(self.registers.len());
+        self.for_each_register(|name, register| registers.push((name.to_string(), register.clone())));
+        registers
+    }
+    
+    // Calls `f` for each register get_all_registers lists, in the same order,
+    // without cloning any of them
+    pub fn for_each_register<'a, F: FnMut(&'a str, &'a ClipboardRegister)>(&'a self, mut f: F) {
+        for (name, register) in self.visible_registers() {
+            f(name, register);
+        }
+    }
+    
+    fn visible_registers(&self) -> impl Iterator<Item = (&String, &ClipboardRegister)> {
+        self.display_names(false).into_iter().map(|name| (name, &self.registers[name]))
+    }
+    
+    // Every register in no particular order, hidden ones only if asked for
+    fn listed_registers(&self, include_hidden: bool) -> impl Iterator<Item = (&String, &ClipboardRegister)> {
+        self.registers.iter().filter(move |(_, register)| include_hidden || !register.hidden)
+    }
+    
+    // get_all_registers with each content cut to its first `max_chars`
+    // characters, plus "…" when anything was cut. Histories are left out;
+    // they hold earlier full contents.
+    pub fn get_all_registers_preview(&self, max_chars: usize) -> Vec<(String, RegisterPreview)> {
+        self.display_names(false).into_iter()
+            .map(|name| {
+                let source = &self.registers[name];
+                let (content, truncated) = match source.content.char_indices().nth(max_chars) {
+                    Some((end, _)) => (format!("{}\u{2026}", &source.content[..end]), true),
+                    None => (source.content.clone(), false)
+                };
+                let register = ClipboardRegister { content, history: Vec::new(), ..source.clone() };
+                (name.clone(), RegisterPreview { register, truncated })
+            })
+            .collect()
+    }
+    
+    // get_all_registers with the hidden registers in their places
+    pub fn get_all_registers_including_hidden(&self) -> Vec<(String, ClipboardRegister)> {
+        self.display_names(true).into_iter()
+            .map(|name| (name.clone(), self.registers[name].clone()))
+            .collect()
+    }
+    
+    // get_all_registers ordered by `sort` instead of `order`; pinned
+    // registers still come first
+    pub fn get_all_registers_sorted(&self, sort: SortOrder) -> Vec<(String, ClipboardRegister)> {
+        self.get_registers_page_sorted(0, usize::MAX, sort)
+    }
+    
+    pub fn get_registers_page_sorted(&self, offset: usize, limit: usize, sort: SortOrder) -> Vec<(String, ClipboardRegister)> {
+        let mut names = self.display_names(false);
+        names.sort_by(|a, b| {
+            let (ra, rb) = (&self.registers[*a], &self.registers[*b]);
+            let by_sort = match sort {
+                SortOrder::Alphabetical => sort_key(a).cmp(&sort_key(b)),
+                SortOrder::Natural => natural_cmp(a, b),
+                SortOrder::Recency => rb.last_used_at.cmp(&ra.last_used_at),
+                SortOrder::UseCount => rb.use_count.cmp(&ra.use_count)
+            };
+            rb.pinned.cmp(&ra.pinned).then(by_sort).then_with(|| a.cmp(b))
+        });
+        
+        names.into_iter()
+            .skip(offset)
+            .take(limit)
+            .map(|name| (name.clone(), self.registers[name].clone()))
+            .collect()
+    }
+    
+    // A window of get_all_registers; only the registers in the window are
+    // cloned. An offset past the end yields an empty page.
+    pub fn get_registers_page(&self, offset: usize, limit: usize) -> Vec<(String, ClipboardRegister)> {
+        self.visible_registers()
+            .skip(offset)
+            .take(limit)
+            .map(|(name, register)| (name.clone(), register.clone()))
+            .collect()
+    }
+    
+    // How many registers get_all_registers lists
+    pub fn visible_len(&self) -> usize {
+        self.registers.values().filter(|r| !r.hidden).count()
+    }
+    
+    // Register names as listed: pinned first, otherwise in `order`
+    fn display_names(&self, include_hidden: bool) -> Vec<&String> {
+        let mut names: Vec<&String> = self.order.iter()
+            .filter(|name| self.registers.get(*name).is_some_and(|r| include_hidden || !r.hidden))
+            .collect();
+        names.sort_by_key(|name| !self.registers[*name].pinned);
+        names
+    }
+    
+    // Records that the register was used (e.g. pasted) without touching its
+    // content or updated_at
+    pub fn mark_used(&mut self, name: &str) -> Result<(), ClipboardError> {
+        let name = &*name_key(name);
+        let now = self.now();
+        let register = self.registers.get_mut(name).ok_or(ClipboardError::NotFound)?;
+        register.last_used_at = Some(now);
+        register.use_count += 1;
+        
+        self.persist()?;
+        
+        Ok(())
+    }
+    
+    // Most recently used first; never-used registers come last. Ties are
+    // broken by name.
+    pub fn get_recently_used(&self, limit: usize, include_hidden: bool) -> Vec<(String, ClipboardRegister)> {
+        let mut registers: Vec<(String, ClipboardRegister)> = self.listed_registers(include_hidden)
+            .map(|(name, register)| (name.clone(), register.clone()))
+            .collect();
+        registers.sort_by(|a, b| {
+            b.1.last_used_at.cmp(&a.1.last_used_at).then_with(|| a.0.cmp(&b.0))
+        });
+        registers.truncate(limit);
+        registers
+    }
+    
+    pub fn get_use_count(&self, name: &str) -> Option<u64> {
+        let name = &*name_key(name);
+        self.registers.get(name).map(|r| r.use_count)
+    }
+    
+    // Highest use_count first, ties broken by name
+    pub fn get_most_used(&self, limit: usize, include_hidden: bool) -> Vec<(String, ClipboardRegister)> {
+        let mut registers: Vec<(String, ClipboardRegister)> = self.listed_registers(include_hidden)
+            .map(|(name, register)| (name.clone(), register.clone()))
+            .collect();
+        registers.sort_by(|a, b| b.1.use_count.cmp(&a.1.use_count).then_with(|| a.0.cmp(&b.0)));
+        registers.truncate(limit);
+        registers
+    }
+    
+    // Allowed whether or not the register is currently locked
+    pub fn set_hidden(&mut self, name: &str, hidden: bool) -> Result<(), ClipboardError> {
+        let name = &*name_key(name);
+        let register = self.registers.get_mut(name).ok_or(ClipboardError::NotFound)?;
+        register.hidden = hidden;
+        
+        self.persist()
+    }
+    
+    pub fn set_locked(&mut self, name: &str, locked: bool) -> Result<(), ClipboardError> {
+        let name = &*name_key(name);
+        let register = self.registers.get_mut(name).ok_or(ClipboardError::NotFound)?;
+        register.locked = locked;
+        
+        self.persist()?;
+        
+        Ok(())
+    }
+    
+    // An empty format clears it
+    pub fn set_format(&mut self, name: &str, format: &str) -> Result<(), ClipboardError> {
+        let name = &*name_key(name);
+        let register = self.registers.get_mut(name).ok_or(ClipboardError::NotFound)?;
+        let format = format.trim();
+        register.format = if format.is_empty() { None } else { Some(format.to_string()) };
+        
+        self.persist()?;
+        
+        Ok(())
+    }
+    
+    // Checks the content against the register's format. Registers without a
+    // format, with a format we don't check, or holding binary data pass.
+    pub fn validate_register(&self, name: &str) -> Result<(), String> {
+        let name = &*name_key(name);
+        let register = self.registers.get(name)
+            .ok_or_else(|| format!("register \"{}\" does not exist", name))?;
+        if register.is_binary() {
+            return Ok(());
+        }
+        
+        match register.format.as_deref() {
+            Some(format) if format.eq_ignore_ascii_case("json") => {
+                serde_json::from_str::<serde_json::Value>(&register.content)
+                    .map(|_| ())
+                    .map_err(|e| format!("invalid JSON: {}", e))
+            },
+            _ => Ok(())
+        }
+    }
+    
+    fn unlocked_mut(&mut self, name: &str) -> Result<&mut ClipboardRegister, ClipboardError> {
+        let register = self.registers.get_mut(name).ok_or(ClipboardError::NotFound)?;
+        if register.locked {
+            return Err(ClipboardError::Locked);
+        }
+        Ok(register)
+    }
+    
+    pub fn set_pinned(&mut self, name: &str, pinned: bool) -> Result<(), ClipboardError> {
+        let name = &*name_key(name);
+        let register = self.registers.get_mut(name).ok_or(ClipboardError::NotFound)?;
+        register.pinned = pinned;
+        
+        self.persist()?;
+        
+        Ok(())
+    }
+    
+    // Moves `name` to `new_index` in the display order; indexes past the end
+    // move it to the end
+    pub fn move_register(&mut self, name: &str, new_index: usize) -> Result<(), ClipboardError> {
+        let name = &*name_key(name);
+        let current = self.order.iter().position(|n| n == name).ok_or(ClipboardError::NotFound)?;
+        let entry = self.order.remove(current);
+        let new_index = new_index.min(self.order.len());
+        self.order.insert(new_index, entry);
+        
+        self.persist()?;
+        
+        Ok(())
+    }
+    
+    // Moves the register to the front of the order. Listings still put
+    // pinned registers first, so it lands above every unpinned one.
+    pub fn promote(&mut self, name: &str) -> Result<(), ClipboardError> {
+        if self.order.first().is_some_and(|first| first == name) {
+            return Ok(());
+        }
+        self.move_register(name, 0)
+    }
+    
+    // Syncs the order of every profile. Configs written before ordering
+    // existed get a sorted order this way.
+    fn sync_order(&mut self) {
+        sync_order(&self.registers, &mut self.order);
+        for profile in self.profiles.values_mut() {
+            sync_order(&profile.registers, &mut profile.order);
+        }
+    }
+    
+    pub fn active_profile(&self) -> &str {
+        &self.active_profile
+    }
+    
+    // Sorted, including the active profile
+    pub fn list_profiles(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.profiles.keys().cloned().collect();
+        names.push(self.active_profile.clone());
+        names.sort();
+        names
+    }
+    
+    // Creates an empty profile without switching to it
+    pub fn create_profile(&mut self, name: &str) -> Result<(), ClipboardError> {
+        let name = clean_name(name)?;
+        if name == self.active_profile || self.profiles.contains_key(&name) {
+            return Err(ClipboardError::DuplicateName);
+        }
+        self.profiles.insert(name, Profile::default());
+        
+        self.persist()?;
+        
+        Ok(())
+    }
+    
+    // Parks the current registers and makes `name` the active profile
+    pub fn switch_profile(&mut self, name: &str) -> Result<(), ClipboardError> {
+        let name = clean_name(name)?;
+        if name == self.active_profile {
+            return Ok(());
+        }
+        let incoming = self.profiles.remove(&name).ok_or(ClipboardError::NotFound)?;
+        
+        let outgoing = Profile {
+            registers: std::mem::replace(&mut self.registers, incoming.registers),
+            order: std::mem::replace(&mut self.order, incoming.order),
+        };
+        let previous = std::mem::replace(&mut self.active_profile, name);
+        self.profiles.insert(previous, outgoing);
+        
+        self.persist()?;
+        
+        Ok(())
+    }
+    
+    // Moves a register from the active profile to the end of another one
+    pub fn move_register_to_profile(&mut self, name: &str, target_profile: &str) -> Result<(), ClipboardError> {
+        let name = &*name_key(name);
+        let target_profile = clean_name(target_profile)?;
+        self.unlocked_mut(name)?;
+        if target_profile == self.active_profile {
+            return Err(ClipboardError::InvalidArgument);
+        }
+        let target = self.profiles.get_mut(&target_profile).ok_or(ClipboardError::NotFound)?;
+        if target.registers.contains_key(name) {
+            return Err(ClipboardError::DuplicateName);
+        }
+        
+        if let Some(register) = self.registers.remove(name) {
+            target.order.push(name.to_string());
+            target.registers.insert(name.to_string(), register);
+        }
+        self.order.retain(|n| n != name);
+        
+        self.persist()?;
+        
+        Ok(())
+    }
+    
+    // Deletes a profile and all of its registers. Switch away from a profile
+    // before deleting it.
+    pub fn delete_profile(&mut self, name: &str) -> Result<(), ClipboardError> {
+        let name = clean_name(name)?;
+        if name == self.active_profile {
+            return Err(ClipboardError::ActiveProfile);
+        }
+        self.profiles.remove(&name).ok_or(ClipboardError::NotFound)?;
+        
+        self.persist()?;
+        
+        Ok(())
+    }
+    
+    // Resolution order: explicit path, then $CLIPBOARD_MANAGER_CONFIG, then ~/
+    // None if no path was given, CONFIG_PATH_ENV is unset and there is no
+    // home directory. There is deliberately no fallback to the current
+    // directory: it is arbitrary for an app and often not writable.
+    fn get_config_path(explicit: Option<PathBuf>) -> Option<PathBuf> {
+        if let Some(path) = explicit {
+            return Some(path);
+        }
+        
+        if let Some(path) = env::var_os(CONFIG_PATH_ENV) {
+            if !path.is_empty() {
+                return Some(PathBuf::from(path));
+            }
+        }
+        
+        let mut path = dirs::home_dir().filter(|home| !home.as_os_str().is_empty())?;
+        path.push(".clipboard_manager_config.json");
+        Some(path)
+    }
+    
+    fn config_file(&self) -> Result<PathBuf, String> {
+        Self::get_config_path(self.config_path.clone()).ok_or_else(no_config_path_error)
+    }
+    
+    // Where saves go, made absolute against the current directory. None if
+    // there is nowhere to save; see get_config_path.
+    pub fn config_path(&self) -> Option<PathBuf> {
+        let path = self.config_file().ok()?;
+        if path.is_relative() {
+            if let Ok(dir) = env::current_dir() {
+                return Some(dir.join(path));
+            }
+        }
+        Some(path)
+    }
+    
+    // An encrypted config is an error here (load spots ENCRYPTED_MAGIC)
+    // rather than unreadable data, so it is never replaced by a plaintext
+    // save; open it with load_encrypted
+    pub fn load_from_disk(path: &Path) -> Result<Self, String> {
+        Self::load(path, None)
+    }
+    
+    // Loads an encrypted config, or a plaintext one which is then encrypted
+    // on the next save. A missing file starts a new encrypted config.
+    pub fn load_encrypted(path: &Path, passphrase: &str) -> Result<Self, String> {
+        Self::load(path, Some(passphrase))
+    }
+    
+    fn load(path: &Path, passphrase: Option<&str>) -> Result<Self, String> {
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let mut state = ClipboardState::with_config_path(path.to_path_buf());
+                state.first_run = true;
+                if let Some(passphrase) = passphrase {
+                    state.encryption = Some(EncryptionKey::generate(passphrase)?);
+                }
+                return Ok(state);
+            }
+            Err(e) => return Err(format!("Failed to read config: {}", e))
+        };
+        
+        let disk_hash = file_fingerprint(&data);
+        let (json, encryption) = if data.starts_with(ENCRYPTED_MAGIC) {
+            let passphrase = passphrase.ok_or("Config is encrypted; a passphrase is required")?;
+            let (key, plaintext) = decrypt_config(&data, passphrase)?;
+            (plaintext, Some(key))
+        } else {
+            let key = match passphrase {
+                Some(passphrase) => Some(EncryptionKey::generate(passphrase)?),
+                None => None
+            };
+            (data, key)
+        };
+        
+        let mut state = ClipboardState::from_config_json(&json)?;
+        state.config_path = Some(path.to_path_buf());
+        state.encryption = encryption;
+        state.disk_hash = Some(disk_hash);
+        Ok(state)
+    }
+    
+    // Replaces the registers and settings with what is on disk, keeping the
+    // config path, encryption key and save mode. On any error the state is
+    // left untouched.
+    pub fn reload(&mut self) -> Result<(), ClipboardError> {
+        let path = self.config_file().map_err(|_| ClipboardError::Io)?;
+        self.replace_from(&path)
+    }
+    
+    // Replaces the whole state with the config stored at `path`, keeping
+    // where and how it is saved. Nothing changes on error.
+    fn replace_from(&mut self, path: &Path) -> Result<(), ClipboardError> {
+        if self.in_memory {
+            return Err(ClipboardError::InvalidArgument);
+        }
+        
+        let data = fs::read(path).map_err(|_| ClipboardError::Io)?;
+        
+        let disk_hash = file_fingerprint(&data);
+        let json = if data.starts_with(ENCRYPTED_MAGIC) {
+            let key = self.encryption.as_ref().ok_or(ClipboardError::InvalidFormat)?;
+            key.decrypt(&data).map_err(|_| ClipboardError::InvalidFormat)?
+        } else {
+            data
+        };
+        let reloaded = ClipboardState::from_config_json(&json).map_err(|_| ClipboardError::InvalidFormat)?;
+        
+        self.replace_with(reloaded);
+        self.disk_hash = Some(disk_hash);
+        Ok(())
+    }
+    
+    // Takes the registers and settings from `other` but keeps where and how
+    // this state is saved
+    fn replace_with(&mut self, mut other: ClipboardState) {
+        other.config_path = self.config_path.take();
+        other.encryption = self.encryption.take();
+        other.defer_saves = self.defer_saves;
+        other.in_memory = self.in_memory;
+        other.disk_hash = self.disk_hash;
+        other.first_run = self.first_run;
+        other.clock = Arc::clone(&self.clock);
+        other.evicted = std::mem::take(&mut self.evicted);
+        other.save_error = self.save_error;
+        *self = other;
+    }
+    
+    // Rolls back to a copy taken earlier with clone(), e.g. by
+    // ClipboardManager::snapshot
+    pub fn restore_snapshot(&mut self, snapshot: ClipboardState) -> Result<(), ClipboardError> {
+        self.replace_with(snapshot);
+        
+        self.persist()
+    }
+    
+    // Applies `ops` in order and saves once. If an op fails, the state is put
+    // back as it was before the batch and the error carries that op's index;
+    // later ops are not run.
+    pub fn apply_batch(&mut self, ops: &[BatchOp]) -> Result<(), BatchError> {
+        let before = self.clone();
+        let evicted_before = self.evicted.len();
+        let defer_saves = std::mem::replace(&mut self.defer_saves, true);
+        
+        let failed = ops.iter().enumerate().find_map(|(index, op)| {
+            self.apply_op(op).err().map(|error| BatchError { index: Some(index), error })
+        });
+        self.defer_saves = defer_saves;
+        
+        if let Some(failure) = failed {
+            self.replace_with(before);
+            self.evicted.truncate(evicted_before);
+            return Err(failure);
+        }
+        self.persist().map_err(|error| BatchError { index: None, error })
+    }
+    
+    fn apply_op(&mut self, op: &BatchOp) -> Result<(), ClipboardError> {
+        match op {
+            BatchOp::Add { name, shortcut } => self.add_register(name.clone(), shortcut.clone()),
+            BatchOp::Remove { name } => self.remove_register(name),
+            BatchOp::Rename { name, new_name } => self.rename_register(name, new_name),
+            BatchOp::SetContent { name, content } => self.update_register_content(name, content.clone()).map(drop),
+            BatchOp::SetShortcut { name, shortcut } => self.update_shortcut(name, shortcut.clone())
+        }
+    }
+    
+    pub fn set_backup_count(&mut self, count: usize) -> Result<(), ClipboardError> {
+        self.backup_count = count;
+        
+        self.persist()
+    }
+    
+    // Backups that currently exist, as (index, path), newest first
+    pub fn list_backups(&self) -> Vec<(usize, PathBuf)> {
+        if self.in_memory {
+            return Vec::new();
+        }
+        
+        let path = match self.config_file() {
+            Ok(path) => path,
+            Err(_) => return Vec::new()
+        };
+        (1..=self.backup_count)
+            .map(|index| (index, backup_path(&path, index)))
+            .filter(|(_, backup)| backup.is_file())
+            .collect()
+    }
+    
+    // Replaces the state with backup `index` (1 is the newest) and saves it.
+    // The config being replaced becomes the newest backup in turn. Backup 1
+    // can be restored even with a backup_count of 0, since reset writes it
+    // when asked to.
+    pub fn restore_backup(&mut self, index: usize) -> Result<(), ClipboardError> {
+        if index == 0 || index > self.backup_count.max(1) {
+            return Err(ClipboardError::InvalidArgument);
+        }
+        
+        let path = self.config_file().map_err(|_| ClipboardError::Io)?;
+        let backup = backup_path(&path, index);
+        if !backup.is_file() {
+            return Err(ClipboardError::NotFound);
+        }
+        self.replace_from(&backup)?;
+        
+        self.persist()
+    }
+    
+    // Factory reset: drops every register, profile, trashed register and
+    // setting, then saves the empty config right away. Where and how the
+    // config is saved (compression and compact output included) is kept, as
+    // is backup_count. With `backup`, the old config is first kept as backup
+    // 1 (so restore_backup(1) undoes the reset) and a failed backup aborts
+    // the reset; this happens even with a backup_count of 0, which is left
+    // as it was. Any error is Io, and then nothing changes in memory.
+    pub fn reset(&mut self, backup: bool) -> Result<(), ClipboardError> {
+        // The backup should hold the latest changes, not a stale file
+        self.flush().map_err(|_| ClipboardError::Io)?;
+        
+        let before = self.clone();
+        let mut fresh = ClipboardState::new();
+        fresh.backup_count = self.backup_count;
+        fresh.compress = self.compress;
+        fresh.compact = self.compact;
+        self.replace_with(fresh);
+        
+        if self.write_config(backup).is_err() {
+            self.replace_with(before);
+            return Err(ClipboardError::Io);
+        }
+        self.dirty = false;
+        Ok(())
+    }
+    
+    // Shifts config.json.1..config.json.<count> up by one, dropping the
+    // oldest, and copies the current config to config.json.1
+    fn rotate_backups(path: &Path, count: usize) -> Result<(), String> {
+        if count == 0 || !path.exists() {
+            return Ok(());
+        }
+        
+        let oldest = backup_path(path, count);
+        if oldest.exists() {
+            fs::remove_file(&oldest).map_err(|e| format!("Failed to remove old backup: {}", e))?;
+        }
+        for index in (1..count).rev() {
+            let from = backup_path(path, index);
+            if from.exists() {
+                fs::rename(&from, backup_path(path, index + 1))
+                    .map_err(|e| format!("Failed to rotate backup: {}", e))?;
+            }
+        }
+        fs::copy(path, backup_path(path, 1)).map_err(|e| format!("Failed to write backup: {}", e))?;
+        Ok(())
+    }
+    
+    // Creates and deletes a scratch file next to the config to prove saves
+    // can succeed. Always Ok for in-memory states.
+    pub fn check_writable(&self) -> Result<(), ClipboardError> {
+        if self.in_memory {
+            return Ok(());
+        }
+        
+        let path = self.config_file().map_err(|_| ClipboardError::Io)?;
+        let dir = path.parent().ok_or(ClipboardError::Io)?;
+        let probe = dir.join(format!(".clipboard_manager_probe_{}", std::process::id()));
+        fs::write(&probe, b"").map_err(|_| ClipboardError::Io)?;
+        let _ = fs::remove_file(&probe);
+        Ok(())
+    }
+    
+    // True if the config file is exactly what this state last wrote or read
+    fn matches_disk(&self) -> bool {
+        match self.config_file().map(fs::read) {
+            Ok(Ok(data)) => self.disk_hash == Some(file_fingerprint(&data)),
+            _ => false
+        }
+    }
+    
+    // Serializes into the versioned on-disk envelope
+    fn to_config_json(&self) -> Result<String, String> {
+        let file = ConfigFile { version: CONFIG_VERSION, state: self };
+        let json = if self.compact {
+            serde_json::to_value(&file).map(|mut value| {
+                strip_register_defaults(&mut value["state"]);
+                value.to_string()
+            })
+        } else {
+            serde_json::to_string_pretty(&file)
+        };
+        json.map_err(|e| format!("Failed to serialize config: {}", e))
+    }
+    
+    // Accepts the current envelope or an unversioned (version 0) file, which
+    // is migrated in memory and written in the current format on next save
+    fn from_config_json(json: &[u8]) -> Result<Self, String> {
+        let inflated;
+        let json = if json.starts_with(COMPRESSED_MAGIC) {
+            inflated = decompress_config(json)?;
+            &inflated[..]
+        } else {
+            json
+        };
+        let value = serde_json::from_slice::<serde_json::Value>(json)
+            .map_err(|e| format!("Failed to parse config: {}", e))?;
+        
+        let mut state = match value.get("version") {
+            None => migrate_v0(value)?,
+            Some(version) => {
+                let version = version.as_u64()
+                    .ok_or_else(|| format!("Config version {} is not a number", version))?;
+                if version != CONFIG_VERSION as u64 {
+                    return Err(format!(
+                        "Config version {} is not supported (expected {}); refusing to load it",
+                        version, CONFIG_VERSION
+                    ));
+                }
+                let state = value.get("state").cloned()
+                    .ok_or_else(|| "Config is missing its \"state\"".to_string())?;
+                serde_json::from_value::<ClipboardState>(state)
+                    .map_err(|e| format!("Failed to parse config: {}", e))?
+            }
+        };
+        
+        normalize_keys(&mut state.registers, &mut state.order);
+        for profile in state.profiles.values_mut() {
+            normalize_keys(&mut profile.registers, &mut profile.order);
+        }
+        state.sync_order();
+        Ok(state)
+    }
+    
+    // Called after every mutation: saves immediately, or marks the state dirty
+    // when saves are deferred
+    fn persist(&mut self) -> Result<(), ClipboardError> {
+        if self.defer_saves {
+            self.dirty = true;
+            return Ok(());
+        }
+        
+        self.save_now()
+    }
+    
+    // A failed save leaves the change in memory and the state dirty, so a
+    // later flush retries it
+    fn save_now(&mut self) -> Result<(), ClipboardError> {
+        match self.save_to_disk() {
+            Ok(()) => {
+                self.dirty = false;
+                Ok(())
+            },
+            Err(_) => {
+                self.dirty = true;
+                self.save_error = Some(ClipboardError::SaveFailed);
+                Err(ClipboardError::SaveFailed)
+            }
+        }
+    }
+    
+    // Takes effect on the next save; either kind of file loads regardless
+    pub fn set_compression(&mut self, compress: bool) -> Result<(), ClipboardError> {
+        self.compress = compress;
+        
+        self.persist()
+    }
+    
+    pub fn set_compact(&mut self, compact: bool) -> Result<(), ClipboardError> {
+        self.compact = compact;
+        
+        self.persist()
+    }
+    
+    // Trims every history to the current limit and rewrites the config right
+    // away, even when saves are deferred
+    pub fn compact(&mut self) -> Result<(), ClipboardError> {
+        let limit = self.history_limit;
+        let parked = self.profiles.values_mut().flat_map(|profile| profile.registers.values_mut());
+        for register in self.registers.values_mut().chain(parked) {
+            trim_history(&mut register.history, limit);
+        }
+        
+        self.save_now()
+    }
+    
+    // Writes any pending deferred changes
+    pub fn flush(&mut self) -> Result<(), ClipboardError> {
+        if self.dirty {
+            self.save_now()?;
+        }
+        Ok(())
+    }
+    
+    pub fn save_to_disk(&mut self) -> Result<(), String> {
+        self.write_config(false)
+    }
+    
+    // With `require_backup`, the previous config is backed up even if
+    // backup_count is 0, and failing to do so fails the save instead of only
+    // being left in save_error
+    fn write_config(&mut self, require_backup: bool) -> Result<(), String> {
+        if self.in_memory {
+            return Ok(());
+        }
+        
+        let path = self.config_file()?;
+        
+        match self.to_config_json() {
+            Ok(json) => {
+                let data = if self.compress {
+                    compress_config(json.as_bytes())?
+                } else {
+                    json.into_bytes()
+                };
+                let data = match &self.encryption {
+                    Some(key) => key.encrypt(&data)?,
+                    None => data
+                };
+                
+                // Write to a sibling temp file and rename it over the config so a
+                // crash mid-write never leaves a truncated file behind
+                let mut tmp_path = path.clone().into_os_string();
+                tmp_path.push(".tmp");
+                let tmp_path = PathBuf::from(tmp_path);
+                let disk_hash = file_fingerprint(&data);
+                
+                if let Err(e) = fs::write(&tmp_path, data) {
+                    let _ = fs::remove_file(&tmp_path);
+                    return Err(format!("Failed to write config: {}", e));
+                }
+                // A failed backup shouldn't cost the user their save; it is
+                // left in save_error instead
+                let count = if require_backup { self.backup_count.max(1) } else { self.backup_count };
+                let backed_up = ClipboardState::rotate_backups(&path, count);
+                if let Err(e) = &backed_up {
+                    if require_backup {
+                        let _ = fs::remove_file(&tmp_path);
+                        return Err(e.clone());
+                    }
+                }
+                if let Err(e) = fs::rename(&tmp_path, &path) {
+                    let _ = fs::remove_file(&tmp_path);
+                    return Err(format!("Failed to replace config: {}", e));
+                }
+                self.disk_hash = Some(disk_hash);
+                self.save_error = backed_up.err().map(|_| ClipboardError::Io);
+                Ok(())
+            },
+            Err(e) => Err(e)
+        }
+    }
+    
+    // Writes this state to an arbitrary file without changing the live config
+    pub fn export_to(&self, path: &Path) -> Result<(), ClipboardError> {
+        let json = self.to_config_json().map_err(|_| ClipboardError::Internal)?;
+        
+        fs::write(path, json).map_err(|_| ClipboardError::Io)
+    }
+    
+    // Loads registers from an exported file. With `replace` the current
+    // registers are discarded, except locked ones; otherwise they are merged,
+    // and on a name clash the existing register is kept unless `overwrite` is
+    // set. A locked register is never overwritten.
+    pub fn import_from(&mut self, path: &Path, replace: bool, overwrite: bool) -> Result<(), ImportError> {
+        let on_conflict = if overwrite { OnConflict::Overwrite } else { OnConflict::Skip };
+        self.import_from_with(path, replace, on_conflict)
+    }
+    
+    // Like import_from, with `on_conflict` deciding what happens to an
+    // imported register whose name is taken
+    pub fn import_from_with(&mut self, path: &Path, replace: bool, on_conflict: OnConflict) -> Result<(), ImportError> {
+        let contents = fs::read(path).map_err(|_| ClipboardError::Io)?;
+        let mut imported = ClipboardState::from_config_json(&contents).map_err(|_| ClipboardError::InvalidFormat)?;
+        
+        if replace {
+            self.clear_unlocked();
+        }
+        for name in imported.order {
+            if let Some(register) = imported.registers.remove(&name) {
+                self.insert_imported(name, register, on_conflict);
+            }
+        }
+        
+        Ok(self.persist()?)
+    }
+    
+    // What a replacing import starts from: every register is dropped except
+    // the locked ones, which keep their place in the order
+    fn clear_unlocked(&mut self) {
+        self.registers.retain(|_, register| register.locked);
+        let registers = &self.registers;
+        self.order.retain(|name| registers.contains_key(name));
+    }
+    
+    // Returns false if the register was skipped. Text content is normalized
+    // when normalize_on_store is set, and content over max_content_bytes is
+    // skipped, as in add_registers. A locked register is never overwritten,
+    // whatever `on_conflict` says. A register added under a new name makes
+    // room first as add_register does, and is skipped if make_room can't.
+    fn insert_imported(&mut self, name: String, mut register: ClipboardRegister, on_conflict: OnConflict) -> bool {
+        if self.normalize_on_store && !register.is_binary() {
+            register.content = normalize_content(&register.content);
+        }
+        if self.max_content_bytes.is_some_and(|max| register.content.len() > max) {
+            return false;
+        }
+        let name = name_key(&name).into_owned();
+        let locked = self.registers.get(&name).is_some_and(|existing| existing.locked);
+        let name = match (self.registers.contains_key(&name), on_conflict) {
+            (false, _) => name,
+            (true, OnConflict::Skip) => return false,
+            (true, OnConflict::Overwrite) if locked => return false,
+            (true, OnConflict::Overwrite) => {
+                self.registers.insert(name, register);
+                return true;
+            },
+            (true, OnConflict::Rename) => self.unique_name(&name)
+        };
+        if self.make_room(None).is_err() {
+            return false;
+        }
+        
+        self.order.push(name.clone());
+        self.registers.insert(name, register);
+        true
+    }
+    
+    // `base` if it is free, otherwise the first free "base (2)", "base (3)", ...
+    pub fn unique_name(&self, base: &str) -> String {
+        let base = &*name_key(base);
+        if !self.registers.contains_key(base) {
+            return base.to_string();
+        }
+        
+        (2..)
+            .map(|n| format!("{} ({})", base, n))
+            .find(|name| !self.registers.contains_key(name))
+            .unwrap_or_default()
+    }
+    
+    // Like add_register, but a taken name gets a " (N)" suffix instead of
+    // failing. Returns the name the register was added under.
+    pub fn add_register_unique(&mut self, base_name: &str, shortcut: &str) -> Result<String, ClipboardError> {
+        let name = self.unique_name(&clean_name(base_name)?);
+        self.add_register(name.clone(), shortcut.to_string())?;
+        Ok(name)
+    }
+    
+    // An XML property list holding an array with one dict per register, in
+    // `order` rather than display order so an import puts pinned registers
+    // back where they were: the register's fields plus its "name"
+    pub fn export_plist(&self) -> Result<String, ClipboardError> {
+        let entries: Vec<ExportedRegister> = self.order.iter()
+            .filter_map(|name| {
+                let register = self.registers.get(name)?.clone();
+                Some(ExportedRegister { name: name.clone(), register })
+            })
+            .collect();
+        let value = serde_json::to_value(&entries).map_err(|_| ClipboardError::Internal)?;
+        
+        let mut out = Vec::new();
+        json_to_plist(value).to_writer_xml(&mut out).map_err(|_| ClipboardError::Internal)?;
+        String::from_utf8(out).map_err(|_| ClipboardError::Internal)
+    }
+    
+    // Reads what export_plist writes. Nothing is changed if the plist can't
+    // be parsed. With `replace` the current registers are discarded, except
+    // locked ones. Registers whose name is still taken are skipped. Returns
+    // the number of registers imported.
+    pub fn import_plist(&mut self, data: &str, replace: bool) -> Result<usize, ImportError> {
+        let value = plist::Value::from_reader_xml(data.as_bytes()).map_err(|_| ClipboardError::InvalidFormat)?;
+        let entries: Vec<ExportedRegister> = serde_json::from_value(plist_to_json(value))
+            .map_err(|_| ClipboardError::InvalidFormat)?;
+        
+        if replace {
+            self.clear_unlocked();
+        }
+        
+        let mut imported = 0;
+        for entry in entries {
+            let name = match clean_name(&entry.name) {
+                Ok(name) => name,
+                Err(_) => continue
+            };
+            if self.insert_imported(name, entry.register, OnConflict::Skip) {
+                imported += 1;
+            }
+        }
+        
+        self.persist()?;
+        
+        Ok(imported)
+    }
+    
+    // Writes one JSON object per line for each register, in `order` as for
+    // export_plist: the register's fields plus its "name". Registers are
+    // serialized one at a time, so the output never has to fit in memory at
+    // once.
+    pub fn export_jsonl<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        for name in &self.order {
+            let register = match self.registers.get(name) {
+                Some(register) => register.clone(),
+                None => continue
+            };
+            let entry = ExportedRegister { name: name.clone(), register };
+            serde_json::to_writer(&mut *writer, &entry)?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()
+    }
+    
+    // Reads what export_jsonl writes; blank lines are ignored. Nothing is
+    // changed if any line is malformed, and the error carries its number.
+    // With `replace` the current registers are discarded, except locked
+    // ones. Registers whose name is still taken are skipped. Returns the
+    // number imported.
+    pub fn import_jsonl<R: BufRead>(&mut self, reader: R, replace: bool) -> Result<usize, ImportError> {
+        let mut entries = Vec::new();
+        for (index, line) in reader.lines().enumerate() {
+            let at_line = |error| ImportError { line: Some(index + 1), error };
+            let line = line.map_err(|_| at_line(ClipboardError::Io))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: ExportedRegister = serde_json::from_str(&line)
+                .map_err(|_| at_line(ClipboardError::InvalidFormat))?;
+            let name = clean_name(&entry.name).map_err(at_line)?;
+            entries.push((name, entry.register));
+        }
+        
+        if replace {
+            self.clear_unlocked();
+        }
+        
+        let mut imported = 0;
+        for (name, register) in entries {
+            if self.insert_imported(name, register, OnConflict::Skip) {
+                imported += 1;
+            }
+        }
+        
+        self.persist()?;
+        
+        Ok(imported)
+    }
+    
+    // One `name,shortcut,content` row per register after a header row,
+    // sorted by name
+    pub fn export_csv(&self) -> String {
+        let mut names: Vec<&String> = self.registers.keys().collect();
+        names.sort();
+        
+        let mut out = String::from("name,shortcut,content\n");
+        for name in names {
+            let register = &self.registers[name];
+            out.push_str(&csv_field(name));
+            out.push(',');
+            out.push_str(&csv_field(&register.shortcut));
+            out.push(',');
+            out.push_str(&csv_field(&register.content));
+            out.push('\n');
+        }
+        out
+    }
+    
+    // Imports `name,shortcut,content` rows (header optional). Nothing is
+    // changed if any row is malformed. With `replace` the current registers
+    // are discarded, except locked ones. Rows whose name is still taken are
+    // skipped. Returns the number of registers imported.
+    pub fn import_csv(&mut self, data: &str, replace: bool) -> Result<usize, ImportError> {
+        self.import_csv_with(data, replace, OnConflict::Skip)
+    }
+    
+    // Like import_csv, with `on_conflict` deciding what happens to a row
+    // whose name is taken
+    pub fn import_csv_with(&mut self, data: &str, replace: bool, on_conflict: OnConflict) -> Result<usize, ImportError> {
+        let mut rows = parse_csv(data)?;
+        if let Some((_, first)) = rows.first() {
+            if first.len() == 3 && first[0] == "name" && first[1] == "shortcut" && first[2] == "content" {
+                rows.remove(0);
+            }
+        }
+        
+        let mut entries = Vec::with_capacity(rows.len());
+        for (line, fields) in rows {
+            let at_line = |error| ImportError { line: Some(line), error };
+            match <[String; 3]>::try_from(fields) {
+                Ok([name, shortcut, content]) => {
+                    let name = clean_name(&name).map_err(at_line)?;
+                    entries.push((name, shortcut, content));
+                },
+                // Not the three fields name,shortcut,content
+                Err(_) => return Err(at_line(ClipboardError::InvalidFormat))
+            }
+        }
+        
+        if replace {
+            self.clear_unlocked();
+        }
+        
+        let mut imported = 0;
+        for (name, shortcut, content) in entries {
+            if self.insert_imported(name, ClipboardRegister::new_at(content, shortcut, self.now()), on_conflict) {
+                imported += 1;
+            }
+        }
+        
+        self.persist()?;
+        
+        Ok(imported)
+    }
+    
+    // Reports problems in the active profile, such as a hand-edited config
+    // might contain, in display order. Read-only; an empty list means the
+    // state is valid.
+    pub fn validate(&self) -> Vec<String> {
+        let mut issues = Vec::new();
+        let mut shortcut_owners: HashMap<String, &str> = HashMap::new();
+        
+        for name in &self.order {
+            let register = match self.registers.get(name) {
+                Some(register) => register,
+                None => continue
+            };
+            
+            if name.trim().is_empty() {
+                issues.push(format!("register name {:?} is empty", name));
+            }
+            
+            if let Some(max) = self.max_content_bytes {
+                if register.content.len() > max {
+                    issues.push(format!(
+                        "register \"{}\" holds {} bytes, over the {} byte limit",
+                        name, register.content.len(), max
+                    ));
+                }
+            }
+            
+            if register.shortcut.is_empty() {
+                continue;
+            }
+            let shortcut = match parse_shortcut(&register.shortcut) {
+                Ok(parsed) => parsed.to_string(),
+                Err(e) => {
+                    issues.push(format!("register \"{}\" has an invalid shortcut: {}", name, e));
+                    register.shortcut.clone()
+                }
+            };
+            match shortcut_owners.get(&shortcut) {
+                Some(owner) => issues.push(format!(
+                    "registers \"{}\" and \"{}\" share the shortcut \"{}\"",
+                    owner, name, register.shortcut
+                )),
+                None => {
+                    shortcut_owners.insert(shortcut, name);
+                }
+            }
+        }
+        
+        issues
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+// Minimal RFC 4180 reader. Returns each record with the (1-based) line it
+// starts on; blank lines are skipped.
+fn parse_csv(data: &str) -> Result<Vec<(usize, Vec<String>)>, ImportError> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut was_quoted = false;
+    let mut line = 1;
+    let mut record_line = 1;
+    let mut chars = data.chars().peekable();
+    
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                },
+                '"' => in_quotes = false,
+                '\n' => {
+                    line += 1;
+                    field.push(c);
+                },
+                _ => field.push(c)
+            }
+            continue;
+        }
+        
+        match c {
+            '"' if field.is_empty() && !was_quoted => {
+                in_quotes = true;
+                was_quoted = true;
+            },
+            // A quote inside an unquoted field
+            '"' => return Err(ImportError { line: Some(line), error: ClipboardError::InvalidFormat }),
+            ',' => {
+                record.push(std::mem::take(&mut field));
+                was_quoted = false;
+            },
+            '\r' if chars.peek() == Some(&'\n') => {},
+            '\n' => {
+                if !record.is_empty() || !field.is_empty() || was_quoted {
+                    record.push(std::mem::take(&mut field));
+                    records.push((record_line, std::mem::take(&mut record)));
+                }
+                was_quoted = false;
+                line += 1;
+                record_line = line;
+            },
+            // Anything but a comma or line break after a closing quote
+            _ if was_quoted => {
+                return Err(ImportError { line: Some(line), error: ClipboardError::InvalidFormat });
+            },
+            _ => field.push(c)
+        }
+    }
+    
+    // An unterminated quoted field
+    if in_quotes {
+        return Err(ImportError { line: Some(record_line), error: ClipboardError::InvalidFormat });
+    }
+    if !record.is_empty() || !field.is_empty() || was_quoted {
+        record.push(field);
+        records.push((record_line, record));
+    }
+    
+    Ok(records)
+}
+
+// Encrypted configs are ENCRYPTED_MAGIC, the argon2 salt, the AES-GCM nonce,
+// then the AES-256-GCM ciphertext of the JSON config
+const ENCRYPTED_MAGIC: &[u8] = b"CMENC1\0";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+#[derive(Clone)]
+struct EncryptionKey {
+    key: [u8; 32],
+    salt: [u8; SALT_LEN],
+}
+
+// Never print key material
+impl fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "EncryptionKey {{ .. }}")
+    }
+}
+
+impl EncryptionKey {
+    fn derive(passphrase: &str, salt: [u8; SALT_LEN]) -> Result<Self, String> {
+        let mut key = [0u8; 32];
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+            .map_err(|e| format!("Failed to derive key: {}", e))?;
+        Ok(EncryptionKey { key, salt })
+    }
+    
+    fn generate(passphrase: &str) -> Result<Self, String> {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        Self::derive(passphrase, salt)
+    }
+    
+    fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, String> {
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&self.key));
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = cipher.encrypt(&nonce, plaintext)
+            .map_err(|_| "Failed to encrypt config".to_string())?;
+        
+        let mut out = Vec::with_capacity(ENCRYPTED_MAGIC.len() + SALT_LEN + NONCE_LEN + ciphertext.len());
+        out.extend_from_slice(ENCRYPTED_MAGIC);
+        out.extend_from_slice(&self.salt);
+        out.extend_from_slice(&nonce);
+        out.extend_from_slice(&ciphertext);
+        Ok(out)
+    }
+    
+    fn decrypt_body(&self, nonce: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, String> {
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&self.key));
+        cipher.decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| "Failed to decrypt config: wrong passphrase or corrupted file".to_string())
+    }
+    
+    // Decrypts a whole config file with this key, without the passphrase.
+    // Only works while the file still uses this key's salt.
+    fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, String> {
+        let body = &data[ENCRYPTED_MAGIC.len()..];
+        if body.len() < SALT_LEN + NONCE_LEN {
+            return Err("Encrypted config is truncated".to_string());
+        }
+        
+        let (salt, rest) = body.split_at(SALT_LEN);
+        if salt != self.salt {
+            return Err("Config was re-encrypted with a different salt; reopen it with the passphrase".to_string());
+        }
+        let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+        self.decrypt_body(nonce, ciphertext)
+    }
+}
+
+// Compressed configs are COMPRESSED_MAGIC followed by a gzip stream of the
+// JSON config. When also encrypted, the compressed bytes are what gets
+// encrypted.
+const COMPRESSED_MAGIC: &[u8] = b"CMGZ1\0";
+
+fn compress_config(json: &[u8]) -> Result<Vec<u8>, String> {
+    let mut encoder = GzEncoder::new(COMPRESSED_MAGIC.to_vec(), Compression::default());
+    encoder.write_all(json)
+        .and_then(|()| encoder.finish())
+        .map_err(|e| format!("Failed to compress config: {}", e))
+}
+
+fn decompress_config(data: &[u8]) -> Result<Vec<u8>, String> {
+    let mut json = Vec::new();
+    GzDecoder::new(&data[COMPRESSED_MAGIC.len()..]).read_to_end(&mut json)
+        .map_err(|e| format!("Failed to decompress config: {}", e))?;
+    Ok(json)
+}
+
+// Returns the key re-derived from the file's salt along with the plaintext
+fn decrypt_config(data: &[u8], passphrase: &str) -> Result<(EncryptionKey, Vec<u8>), String> {
+    let body = &data[ENCRYPTED_MAGIC.len()..];
+    if body.len() < SALT_LEN + NONCE_LEN {
+        return Err("Encrypted config is truncated".to_string());
+    }
+    
+    let (salt_bytes, rest) = body.split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    let mut salt = [0u8; SALT_LEN];
+    salt.copy_from_slice(salt_bytes);
+    
+    let key = EncryptionKey::derive(passphrase, salt)?;
+    let plaintext = key.decrypt_body(nonce, ciphertext)?;
+    
+    Ok((key, plaintext))
+}
+
+// Modifier bits of Shortcut::modifiers
+pub const MODIFIER_CMD: u32 = 1 << 0;
+pub const MODIFIER_CTRL: u32 = 1 << 1;
+pub const MODIFIER_ALT: u32 = 1 << 2;
+pub const MODIFIER_SHIFT: u32 = 1 << 3;
+
+// Canonical order and spelling used when normalizing
+const MODIFIER_NAMES: [(u32, &str); 4] = [
+    (MODIFIER_CMD, "Cmd"),
+    (MODIFIER_CTRL, "Ctrl"),
+    (MODIFIER_ALT, "Alt"),
+    (MODIFIER_SHIFT, "Shift"),
+];
+
+const NAMED_KEYS: [&str; 14] = [
+    "Space", "Tab", "Return", "Escape", "Delete", "ForwardDelete",
+    "Up", "Down", "Left", "Right", "Home", "End", "PageUp", "PageDown",
+];
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Shortcut {
+    pub modifiers: u32,
+    pub key: String,
+}
+
+impl fmt::Display for Shortcut {
+    // The normalized form, e.g. "Cmd+Shift+K"
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (bit, name) in MODIFIER_NAMES {
+            if self.modifiers & bit != 0 {
+                write!(f, "{}+", name)?;
+            }
+        }
+        write!(f, "{}", self.key)
+    }
+}
+
+fn parse_modifier(token: &str) -> Option<u32> {
+    match token.to_lowercase().as_str() {
+        "cmd" | "command" | "⌘" => Some(MODIFIER_CMD),
+        "ctrl" | "control" | "⌃" => Some(MODIFIER_CTRL),
+        "alt" | "opt" | "option" | "⌥" => Some(MODIFIER_ALT),
+        "shift" | "⇧" => Some(MODIFIER_SHIFT),
+        _ => None
+    }
+}
+
+fn parse_key(token: &str) -> Option<String> {
+    let mut chars = token.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return if c.is_control() || c.is_whitespace() { None } else { Some(c.to_uppercase().collect()) };
+    }
+    
+    let lower = token.to_lowercase();
+    if let Some(named) = NAMED_KEYS.iter().find(|k| k.to_lowercase() == lower) {
+        return Some(named.to_string());
+    }
+    
+    let number = lower.strip_prefix('f')?.parse::<u8>().ok()?;
+    if (1..=20).contains(&number) {
+        Some(format!("F{}", number))
+    } else {
+        None
+    }
+}
+
+// Parses `Modifier+...+Key`, e.g. "cmd+shift+k". Modifiers (Cmd, Ctrl, Alt,
+// Shift and common aliases) are case-insensitive and may appear in any order,
+// but each at most once, followed by exactly one key.
+pub fn parse_shortcut(s: &str) -> Result<Shortcut, String> {
+    let tokens: Vec<&str> = s.split('+').map(|t| t.trim()).collect();
+    let (key_token, modifier_tokens) = tokens.split_last()
+        .ok_or_else(|| "shortcut is empty".to_string())?;
+    
+    let mut modifiers = 0;
+    for token in modifier_tokens {
+        if token.is_empty() {
+            return Err(format!("empty key in shortcut \"{}\"", s));
+        }
+        let bit = parse_modifier(token)
+            .ok_or_else(|| format!("\"{}\" is not a modifier; a shortcut has exactly one key", token))?;
+        if modifiers & bit != 0 {
+            return Err(format!("modifier \"{}\" appears more than once", token));
+        }
+        modifiers |= bit;
+    }
+    
+    if key_token.is_empty() {
+        return Err(format!("shortcut \"{}\" has no key", s));
+    }
+    if parse_modifier(key_token).is_some() {
+        return Err(format!("shortcut \"{}\" has no key after the modifiers", s));
+    }
+    let key = parse_key(key_token).ok_or_else(|| format!("unknown key \"{}\"", key_token))?;
+    
+    Ok(Shortcut { modifiers, key })
+}
+
+// Comparison key for shortcuts: the normalized form if it parses, otherwise
+// the string as stored
+fn shortcut_key(shortcut: &str) -> String {
+    parse_shortcut(shortcut)
+        .map(|parsed| parsed.to_string())
+        .unwrap_or_else(|_| shortcut.to_string())
+}
+
+// Empty shortcuts (no binding) are passed through; anything else must parse
+fn normalize_shortcut(shortcut: &str) -> Result<String, ClipboardError> {
+    if shortcut.is_empty() {
+        return Ok(String::new());
+    }
+    parse_shortcut(shortcut)
+        .map(|parsed| parsed.to_string())
+        .map_err(|_| ClipboardError::InvalidShortcut)
+}
+
+// Version of the on-disk format, stored as {"version": N, "state": {...}}.
+// Version 0 is the original unversioned serialization of ClipboardState.
+pub const CONFIG_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct ConfigFile<'a> {
+    version: u32,
+    state: &'a ClipboardState,
+}
+
+fn migrate_v0(value: serde_json::Value) -> Result<ClipboardState, String> {
+    serde_json::from_value::<ClipboardState>(value)
+        .map_err(|e| format!("Failed to parse unversioned config: {}", e))
+}
+
+// Which register of a duplicate group dedupe keeps; the discriminants are the
+// integers passed over FFI
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DedupeKeep {
+    // The one listed first
+    #[default]
+    First = 0,
+    // The first pinned one, or the first if none is pinned
+    Pinned = 1,
+    // The most recently updated one
+    MostRecent = 2,
+}
+
+impl DedupeKeep {
+    pub fn from_c_int(value: c_int) -> Option<DedupeKeep> {
+        match value {
+            0 => Some(DedupeKeep::First),
+            1 => Some(DedupeKeep::Pinned),
+            2 => Some(DedupeKeep::MostRecent),
+            _ => None
+        }
+    }
+}
+
+// Search modes; the discriminants are the integers passed over FFI
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SearchMode {
+    // Name equals the query
+    Exact = 0,
+    // Name starts with the query
+    Prefix = 1,
+    // Name or text content contains the query
+    Substring = 2,
+}
+
+impl SearchMode {
+    pub fn from_c_int(mode: c_int) -> Option<SearchMode> {
+        match mode {
+            0 => Some(SearchMode::Exact),
+            1 => Some(SearchMode::Prefix),
+            2 => Some(SearchMode::Substring),
+            _ => None
+        }
+    }
+}
+
+// Orders for the *_sorted listings; the discriminants are the integers passed
+// over FFI
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortOrder {
+    // By name, ignoring case and accents ("Émile" sorts with "emile")
+    #[default]
+    Alphabetical = 0,
+    // Alphabetical, but runs of digits compare as numbers ("file2" before
+    // "file10")
+    Natural = 1,
+    // Most recently used first; never-used registers last
+    Recency = 2,
+    // Highest use_count first
+    UseCount = 3,
+}
+
+impl SortOrder {
+    pub fn from_c_int(value: c_int) -> Option<SortOrder> {
+        match value {
+            0 => Some(SortOrder::Alphabetical),
+            1 => Some(SortOrder::Natural),
+            2 => Some(SortOrder::Recency),
+            3 => Some(SortOrder::UseCount),
+            _ => None
+        }
+    }
+}
+
+// Lowercased with accents stripped, so names compare the way people read
+// them rather than by code point
+fn sort_key(s: &str) -> String {
+    s.nfd().filter(|c| !is_combining_mark(*c)).flat_map(char::to_lowercase).collect()
+}
+
+// Compares alternating runs of digits and non-digits; digit runs compare by
+// value (leading zeros ignored), the rest by sort_key
+fn natural_cmp(a: &str, b: &str) -> std::cmp::Ordering {
+    let (a, b) = (sort_key(a), sort_key(b));
+    let (mut a, mut b) = (a.as_str(), b.as_str());
+    while !a.is_empty() && !b.is_empty() {
+        let (run_a, rest_a) = split_run(a);
+        let (run_b, rest_b) = split_run(b);
+        let digits = run_a.starts_with(|c: char| c.is_ascii_digit())
+            && run_b.starts_with(|c: char| c.is_ascii_digit());
+        let ordering = if digits {
+            let (num_a, num_b) = (run_a.trim_start_matches('0'), run_b.trim_start_matches('0'));
+            num_a.len().cmp(&num_b.len()).then_with(|| num_a.cmp(num_b))
+        } else {
+            run_a.cmp(run_b)
+        };
+        if ordering.is_ne() {
+            return ordering;
+        }
+        (a, b) = (rest_a, rest_b);
+    }
+    a.len().cmp(&b.len())
+}
+
+// Splits off the leading run of ASCII digits, or of anything else
+fn split_run(s: &str) -> (&str, &str) {
+    let digit = s.starts_with(|c: char| c.is_ascii_digit());
+    let end = s.find(|c: char| c.is_ascii_digit() != digit).unwrap_or(s.len());
+    s.split_at(end)
+}
+
+// What an import does with a register whose name is already taken; the
+// discriminants are the integers passed over FFI
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OnConflict {
+    // Keep the existing register and drop the imported one
+    Skip = 0,
+    // Replace the existing register, keeping its place in the order
+    Overwrite = 1,
+    // Add the imported one under a free "name (N)", see unique_name
+    Rename = 2,
+}
+
+impl OnConflict {
+    pub fn from_c_int(value: c_int) -> Option<OnConflict> {
+        match value {
+            0 => Some(OnConflict::Skip),
+            1 => Some(OnConflict::Overwrite),
+            2 => Some(OnConflict::Rename),
+            _ => None
+        }
+    }
+}
+
+// What set_register did; the discriminants are the codes returned over FFI
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SetOutcome {
+    Created = 0,
+    Updated = 1,
+}
+
+// Replaces each `{key}` with vars[key]. Placeholders with no matching
+// variable are left as written, and `{{` produces a literal `{`.
+fn expand_template(template: &str, vars: &HashMap<String, String>) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        
+        if rest.starts_with("{{") {
+            out.push('{');
+            rest = &rest[2..];
+            continue;
+        }
+        
+        let key = rest[1..].find(['{', '}'])
+            .filter(|&end| rest.as_bytes()[end + 1] == b'}')
+            .map(|end| &rest[1..end + 1]);
+        match key.and_then(|key| vars.get(key).map(|value| (key, value))) {
+            Some((key, value)) => {
+                out.push_str(value);
+                rest = &rest[key.len() + 2..];
+            },
+            None => {
+                out.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+// Replaces `{{date}}` (2024-03-01), `{{time}}` (14:05:09), `{{datetime}}`
+// (both) and `{{date:FORMAT}}` with a strftime FORMAT. Unknown tokens are
+// left as written, and `{{{{` produces a literal `{{`. None if a FORMAT is
+// invalid.
+fn render_time_tokens(text: &str, now: &DateTime<Local>) -> Option<String> {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        
+        if rest.starts_with("{{{{") {
+            out.push_str("{{");
+            rest = &rest[4..];
+            continue;
+        }
+        
+        let token = rest[2..].find("}}").map(|end| &rest[2..end + 2]);
+        let format = match token {
+            Some("date") => Some("%Y-%m-%d"),
+            Some("time") => Some("%H:%M:%S"),
+            Some("datetime") => Some("%Y-%m-%d %H:%M:%S"),
+            Some(token) => token.strip_prefix("date:"),
+            None => None
+        };
+        match token.zip(format) {
+            Some((token, format)) => {
+                let items: Vec<Item> = StrftimeItems::new(format).collect();
+                if items.contains(&Item::Error) {
+                    return None;
+                }
+                out.push_str(&now.format_with_items(items.into_iter()).to_string());
+                rest = &rest[token.len() + 4..];
+            },
+            None => {
+                out.push_str("{{");
+                rest = &rest[2..];
+            }
+        }
+    }
+    out.push_str(rest);
+    Some(out)
+}
+
+// Lets ClipboardManager::mutate tell a rejected change from one that was
+// applied in memory but not saved
+trait MutationError {
+    fn applied(&self) -> bool;
+    // For failures before `f` runs, such as a Busy lock
+    fn from_error(e: ClipboardError) -> Self;
+}
+
+impl MutationError for ClipboardError {
+    fn applied(&self) -> bool {
+        *self == ClipboardError::SaveFailed
+    }
+    
+    fn from_error(e: ClipboardError) -> Self {
+        e
+    }
+}
+
+impl MutationError for ImportError {
+    fn applied(&self) -> bool {
+        self.line.is_none() && self.error.applied()
+    }
+    
+    fn from_error(error: ClipboardError) -> Self {
+        ImportError { line: None, error }
+    }
+}
+
+impl MutationError for BatchError {
+    fn applied(&self) -> bool {
+        self.index.is_none() && self.error.applied()
+    }
+    
+    fn from_error(error: ClipboardError) -> Self {
+        BatchError { index: None, error }
+    }
+}
+
+fn batch_report(count: usize, result: Result<(), BatchError>) -> serde_json::Value {
+    let failure = match result {
+        Ok(()) => return serde_json::json!({ "ok": true, "results": vec!["applied"; count] }),
+        Err(failure) => failure
+    };
+    
+    let results: Vec<&str> = (0..count).map(|i| match failure.index {
+        None if failure.applied() => "applied",
+        None => "skipped",
+        Some(index) if i < index => "rolled_back",
+        Some(index) if i == index => "failed",
+        Some(_) => "skipped"
+    }).collect();
+    serde_json::json!({
+        "ok": false,
+        "error": failure.error as c_int,
+        "message": failure.error.to_string(),
+        "results": results
+    })
+}
+
+fn took_effect<T, E: MutationError>(result: &Result<T, E>) -> bool {
+    match result {
+        Ok(_) => true,
+        Err(e) => e.applied()
+    }
+}
+
+// Expands `$NAME` and `${NAME}`, where NAME is letters, digits and
+// underscores not starting with a digit. References to unset variables are
+// kept as written, and `\$` produces a literal `$`.
+fn expand_env(text: &str) -> String {
+    let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    
+    while let Some(index) = rest.find(['\\', '$']) {
+        out.push_str(&rest[..index]);
+        rest = &rest[index..];
+        
+        if rest.starts_with("\\$") {
+            out.push('$');
+            rest = &rest[2..];
+            continue;
+        }
+        if rest.starts_with('\\') {
+            out.push('\\');
+            rest = &rest[1..];
+            continue;
+        }
+        
+        let (name, len) = if rest[1..].starts_with('{') {
+            match rest[2..].find('}') {
+                Some(end) => (&rest[2..end + 2], end + 3),
+                None => ("", 0)
+            }
+        } else {
+            let end = rest[1..].find(|c: char| !is_name_char(c)).unwrap_or(rest.len() - 1);
+            (&rest[1..end + 1], end + 1)
+        };
+        
+        let valid = !name.is_empty()
+            && name.chars().all(is_name_char)
+            && !name.starts_with(|c: char| c.is_ascii_digit());
+        match env::var(name).ok().filter(|_| valid) {
+            Some(value) => {
+                out.push_str(&value);
+                rest = &rest[len..];
+            },
+            None => {
+                out.push('$');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+// Removes register fields that deserialize to their default when absent:
+// nulls, empty lists, false and zero
+fn strip_register_defaults(state: &mut serde_json::Value) {
+    strip_defaults_in(&mut state["registers"]);
+    if let Some(profiles) = state.get_mut("profiles").and_then(|p| p.as_object_mut()) {
+        for profile in profiles.values_mut() {
+            strip_defaults_in(&mut profile["registers"]);
+        }
+    }
+}
+
+fn strip_defaults_in(registers: &mut serde_json::Value) {
+    let registers = match registers.as_object_mut() {
+        Some(registers) => registers,
+        None => return
+    };
+    for register in registers.values_mut().filter_map(|r| r.as_object_mut()) {
+        register.retain(|_, field| match field {
+            serde_json::Value::Null => false,
+            serde_json::Value::Bool(b) => *b,
+            serde_json::Value::Number(n) => n.as_u64() != Some(0),
+            serde_json::Value::Array(items) => !items.is_empty(),
+            _ => true
+        });
+    }
+}
+
+// Size of a register's content. Words are runs of non-whitespace; a final
+// newline does not start another line. Binary registers only report the
+// decoded byte count.
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RegisterStats {
+    pub chars: usize,
+    pub bytes: usize,
+    pub words: usize,
+    pub lines: usize,
+}
+
+// One step of ClipboardState::apply_batch, parsed from e.g.
+// {"op": "rename", "name": "a", "new_name": "b"}
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum BatchOp {
+    Add {
+        name: String,
+        #[serde(default)]
+        shortcut: String,
+    },
+    Remove { name: String },
+    Rename { name: String, new_name: String },
+    SetContent { name: String, content: String },
+    SetShortcut { name: String, shortcut: String },
+}
+
+impl BatchOp {
+    // Every register name the op touches
+    fn names(&self) -> Vec<&str> {
+        match self {
+            BatchOp::Rename { name, new_name } => vec![name, new_name],
+            BatchOp::Add { name, .. }
+            | BatchOp::Remove { name }
+            | BatchOp::SetContent { name, .. }
+            | BatchOp::SetShortcut { name, .. } => vec![name]
+        }
+    }
+    
+    // The name that no longer exists once the op is applied, if any
+    fn removed_name(&self) -> Option<&str> {
+        match self {
+            BatchOp::Remove { name } => Some(name),
+            BatchOp::Rename { name, new_name } if name_key(name) != name_key(new_name.trim()) => Some(name),
+            _ => None
+        }
+    }
+}
+
+// Why apply_batch failed. With an `index`, that op was rejected and the whole
+// batch rolled back; without one, every op applied but `error` came from
+// saving (or taking the lock).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BatchError {
+    pub index: Option<usize>,
+    pub error: ClipboardError,
+}
+
+// Why an import failed. With a `line`, that line of the input was malformed
+// and nothing was imported; without one, `error` came from reading the
+// input, saving or taking the lock.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ImportError {
+    pub line: Option<usize>,
+    pub error: ClipboardError,
+}
+
+impl From<ClipboardError> for ImportError {
+    fn from(error: ClipboardError) -> Self {
+        ImportError { line: None, error }
+    }
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}", line, self.error),
+            None => write!(f, "{}", self.error)
+        }
+    }
+}
+
+// One line of a diff, serialized as {"op": "added" | "removed" |
+// "unchanged", "line": "..."}
+#[derive(Serialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "op", content = "line", rename_all = "lowercase")]
+pub enum DiffLine {
+    Added(String),
+    Removed(String),
+    Unchanged(String),
+}
+
+// Line diff via the longest common subsequence; removals are listed before
+// additions where lines were replaced
+fn diff_lines(old: &str, new: &str) -> Vec<DiffLine> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    
+    // lcs[i][j] is the LCS length of old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    
+    let mut diff = Vec::with_capacity(old.len().max(new.len()));
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            diff.push(DiffLine::Unchanged(old[i].to_string()));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            diff.push(DiffLine::Removed(old[i].to_string()));
+            i += 1;
+        } else {
+            diff.push(DiffLine::Added(new[j].to_string()));
+            j += 1;
+        }
+    }
+    diff.extend(old[i..].iter().map(|line| DiffLine::Removed(line.to_string())));
+    diff.extend(new[j..].iter().map(|line| DiffLine::Added(line.to_string())));
+    diff
+}
+
+// Levenshtein distance between `a` and `b`, or None once it is certain to
+// exceed `max`
+fn bounded_levenshtein(a: &[char], b: &[char], max: usize) -> Option<usize> {
+    if a.len().abs_diff(b.len()) > max {
+        return None;
+    }
+    
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut curr = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        curr[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = prev[j] + usize::from(ca != cb);
+            curr[j + 1] = substitute.min(prev[j + 1] + 1).min(curr[j] + 1);
+        }
+        if curr.iter().all(|&d| d > max) {
+            return None;
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+    
+    let distance = prev[b.len()];
+    if distance <= max { Some(distance) } else { None }
+}
+
+// config.json -> config.json.<index>
+fn backup_path(path: &Path, index: usize) -> PathBuf {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(format!(".{}", index));
+    PathBuf::from(backup)
+}
+
+// Cheap equality check for config file contents; not stable across builds
+// and never persisted
+fn file_fingerprint(data: &[u8]) -> u64 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    data.hash(&mut hasher);
+    hasher.finish()
+}
+
+// A register serialized as one flat object with its name alongside the
+// other fields: {"name": "...", "content": "...", "shortcut": "...", ...}
+#[derive(Serialize)]
+struct NamedRegister<'a> {
+    name: &'a str,
+    #[serde(flatten)]
+    register: HashedRegister<'a>,
+}
+
+// A register from get_all_registers_preview, with an empty history;
+// `truncated` says whether its content was shortened
+#[derive(Serialize, Clone, Debug)]
+pub struct RegisterPreview {
+    #[serde(flatten)]
+    pub register: ClipboardRegister,
+    pub truncated: bool,
+}
+
+// A register in an export_plist array or an export_jsonl line
+#[derive(Serialize, Deserialize)]
+struct ExportedRegister {
+    name: String,
+    #[serde(flatten)]
+    register: ClipboardRegister,
+}
+
+// Property lists have no null, so null fields (unset options) are left out
+// and read back as their default
+fn json_to_plist(value: serde_json::Value) -> plist::Value {
+    match value {
+        serde_json::Value::Null => plist::Value::String(String::new()),
+        serde_json::Value::Bool(b) => plist::Value::Boolean(b),
+        serde_json::Value::Number(n) => match n.as_u64() {
+            Some(n) => plist::Value::Integer(n.into()),
+            None => match n.as_i64() {
+                Some(n) => plist::Value::Integer(n.into()),
+                None => plist::Value::Real(n.as_f64().unwrap_or_default())
+            }
+        },
+        serde_json::Value::String(s) => plist::Value::String(s),
+        serde_json::Value::Array(items) => plist::Value::Array(items.into_iter().map(json_to_plist).collect()),
+        serde_json::Value::Object(fields) => plist::Value::Dictionary(
+            fields.into_iter()
+                .filter(|(_, field)| !field.is_null())
+                .map(|(key, field)| (key, json_to_plist(field)))
+                .collect()
+        )
+    }
+}
+
+fn plist_to_json(value: plist::Value) -> serde_json::Value {
+    match value {
+        plist::Value::Boolean(b) => serde_json::Value::Bool(b),
+        plist::Value::Integer(n) => match n.as_unsigned() {
+            Some(n) => n.into(),
+            None => n.as_signed().map_or(serde_json::Value::Null, Into::into)
+        },
+        plist::Value::Real(n) => n.into(),
+        plist::Value::String(s) => serde_json::Value::String(s),
+        plist::Value::Array(items) => items.into_iter().map(plist_to_json).collect(),
+        plist::Value::Dictionary(fields) => serde_json::Value::Object(
+            fields.into_iter().map(|(key, field)| (key, plist_to_json(field))).collect()
+        ),
+        _ => serde_json::Value::Null
+    }
+}
+
+// A register with its content_hash added, for listings a sync layer can
+// diff without pulling every body
+#[derive(Serialize)]
+struct HashedRegister<'a> {
+    #[serde(flatten)]
+    register: &'a ClipboardRegister,
+    content_hash: String,
+}
+
+impl<'a> HashedRegister<'a> {
+    fn new(register: &'a ClipboardRegister) -> Self {
+        HashedRegister { register, content_hash: register.content_hash() }
+    }
+}
+
+// This object will be shared with Swift via FFI
+pub struct ClipboardManager {
+    state: Arc<RwLock<ClipboardState>>,
+    // Shared with the auto-reload thread, which notifies after a reload
+    observers: Arc<Observers>,
+    flusher: Mutex<Option<Flusher>>,
+    reloader: Mutex<Option<AutoReloader>>,
+    // Milliseconds to wait for the state lock before giving up with Busy;
+    // 0 waits indefinitely
+    lock_timeout_ms: AtomicU64,
+    // Whether JSON handed out by the manager is indented; see to_json
+    output_pretty: AtomicBool,
+    // Copied from the state at construction; it never changes afterwards
+    first_run: bool,
+}
+
+// Default for ClipboardManager::set_lock_timeout
+pub const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_millis(500);
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(1);
+
+// Background thread that writes deferred changes at most once per interval
+struct Flusher {
+    stop: Sender<()>,
+    handle: JoinHandle<()>,
+}
+
+impl Flusher {
+    fn spawn(state: Arc<RwLock<ClipboardState>>, interval: Duration) -> Self {
+        let (stop, stop_rx) = mpsc::channel();
+        let handle = thread::spawn(move || loop {
+            let stopping = !matches!(stop_rx.recv_timeout(interval), Err(RecvTimeoutError::Timeout));
+            
+            // Check under a read lock first so idle ticks don't block readers
+            let dirty = state.read().unwrap_or_else(PoisonError::into_inner).dirty;
+            // A failure leaves the state dirty, so the next tick retries, and
+            // is reported by flush() and health_check meanwhile
+            if dirty {
+                let mut state = state.write().unwrap_or_else(PoisonError::into_inner);
+                let _ = state.flush();
+            }
+            
+            if stopping {
+                break;
+            }
+        });
+        
+        Flusher { stop, handle }
+    }
+    
+    fn stop(self) {
+        let _ = self.stop.send(());
+        let _ = self.handle.join();
+    }
+}
+
+// Called with the name of a register that was added, removed, or changed,
+// plus the user_data pointer given at registration. An empty name means
+// many registers may have changed at once (e.g. after an import).
+pub type ChangeCallbackFn = extern "C" fn(name: *const c_char, user_data: *mut c_void);
+
+#[derive(Clone, Copy)]
+struct ChangeCallback {
+    func: ChangeCallbackFn,
+    user_data: *mut c_void,
+}
+
+// user_data is opaque to us; the embedder is responsible for it being
+// usable from whichever thread performs the mutation.
+unsafe impl Send for ChangeCallback {}
+
+// Change callbacks and the revision counter
+struct Observers {
+    callback: Mutex<Option<ChangeCallback>>,
+    // Fired only for changes to the register they are keyed by, after the
+    // global callback
+    register_callbacks: Mutex<HashMap<String, ChangeCallback>>,
+    // Bumped after every successful mutation so embedders can poll for
+    // changes without taking the state lock. In-memory only.
+    revision: AtomicU64,
+}
+
+impl Observers {
+    fn new() -> Self {
+        Observers {
+            callback: Mutex::new(None),
+            register_callbacks: Mutex::new(HashMap::new()),
+            revision: AtomicU64::new(0),
+        }
+    }
+    
+    // Callbacks are copied out before being invoked so they may re-register
+    // themselves without deadlocking
+    fn notify(&self, name: &str) {
+        let mut targets: Vec<ChangeCallback> = Vec::new();
+        targets.extend(*self.callback.lock().unwrap_or_else(PoisonError::into_inner));
+        {
+            let callbacks = self.register_callbacks.lock().unwrap_or_else(PoisonError::into_inner);
+            if name.is_empty() {
+                targets.extend(callbacks.values().copied());
+            } else {
+                targets.extend(callbacks.get(&*name_key(name)).copied());
+            }
+        }
+        
+        if let Ok(name) = CString::new(name) {
+            for callback in targets {
+                (callback.func)(name.as_ptr(), callback.user_data);
+            }
+        }
+    }
+    
+    fn drop_register_callback(&self, name: &str) {
+        self.register_callbacks.lock().unwrap_or_else(PoisonError::into_inner).remove(&*name_key(name));
+    }
+    
+    fn clear(&self) {
+        *self.callback.lock().unwrap_or_else(PoisonError::into_inner) = None;
+        self.register_callbacks.lock().unwrap_or_else(PoisonError::into_inner).clear();
+    }
+}
+
+// Quiet period after the last file event before reloading. Editors and sync
+// tools often write a file in several steps.
+const RELOAD_DEBOUNCE: Duration = Duration::from_millis(250);
+
+enum WatchMessage {
+    Changed,
+    Stop,
+}
+
+// Watches the config file and reloads the state when something else changes
+// it. A file identical to what the state last wrote or read is ignored, so
+// our own saves never trigger a reload.
+struct AutoReloader {
+    watcher: RecommendedWatcher,
+    stop: Sender<WatchMessage>,
+    handle: JoinHandle<()>,
+}
+
+impl AutoReloader {
+    fn spawn(state: Arc<RwLock<ClipboardState>>, observers: Arc<Observers>, path: PathBuf) -> Result<Self, String> {
+        let file_name = path.file_name()
+            .ok_or_else(|| format!("Config path {} has no file name", path.display()))?
+            .to_os_string();
+        let (tx, rx) = mpsc::channel();
+        
+        let events = tx.clone();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            if let Ok(event) = event {
+                if event.paths.iter().any(|p| p.file_name() == Some(file_name.as_os_str())) {
+                    let _ = events.send(WatchMessage::Changed);
+                }
+            }
+        }).map_err(|e| format!("Failed to watch config: {}", e))?;
+        
+        // Saves rename a temp file over the config, which would orphan a watch
+        // on the file itself, so watch its directory instead
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new(".")
+        };
+        watcher.watch(dir, RecursiveMode::NonRecursive)
+            .map_err(|e| format!("Failed to watch {}: {}", dir.display(), e))?;
+        
+        let handle = thread::spawn(move || {
+            while let Ok(WatchMessage::Changed) = rx.recv() {
+                loop {
+                    match rx.recv_timeout(RELOAD_DEBOUNCE) {
+                        Ok(WatchMessage::Changed) => continue,
+                        Err(RecvTimeoutError::Timeout) => break,
+                        Ok(WatchMessage::Stop) | Err(RecvTimeoutError::Disconnected) => return
+                    }
+                }
+                
+                let reloaded = {
+                    let mut state = state.write().unwrap_or_else(PoisonError::into_inner);
+                    !state.matches_disk() && state.reload().is_ok()
+                };
+                if reloaded {
+                    observers.revision.fetch_add(1, Ordering::SeqCst);
+                    observers.notify("");
+                }
+            }
+        });
+        
+        Ok(AutoReloader { watcher, stop: tx, handle })
+    }
+    
+    fn stop(self) {
+        drop(self.watcher);
+        let _ = self.stop.send(WatchMessage::Stop);
+        let _ = self.handle.join();
+    }
+}
+
+impl ClipboardManager {
+    // With nowhere to keep the config (no path, no CONFIG_PATH_ENV and no
+    // home directory) this starts empty and every save fails with an error.
+    // A config that exists but can't be loaded (corrupt, from a newer
+    // version, or encrypted) is an error, so it is never saved over.
+    pub fn new(config_path: Option<PathBuf>) -> Result<Self, String> {
+        let path = match ClipboardState::get_config_path(config_path) {
+            Some(path) => path,
+            None => {
+                eprintln!("{}", no_config_path_error());
+                return Ok(ClipboardManager::from_state(ClipboardState::new()));
+            }
+        };
+        let state = ClipboardState::load_from_disk(&path)?;
+        
+        Ok(ClipboardManager::from_state(state))
+    }
+    
+    // Unlike `new`, a config that can't be decrypted or parsed is an error
+    // rather than being replaced by an empty state
+    pub fn new_encrypted(config_path: Option<PathBuf>, passphrase: &str) -> Result<Self, String> {
+        let path = ClipboardState::get_config_path(config_path).ok_or_else(no_config_path_error)?;
+        let state = ClipboardState::load_encrypted(&path, passphrase)?;
+        Ok(ClipboardManager::from_state(state))
+    }
+    
+    // Nothing is loaded and nothing is ever saved
+    pub fn new_in_memory() -> Self {
+        ClipboardManager::from_state(ClipboardState::in_memory())
+    }
+    
+    fn from_state(state: ClipboardState) -> Self {
+        let first_run = state.is_first_run();
+        ClipboardManager {
+            state: Arc::new(RwLock::new(state)),
+            observers: Arc::new(Observers::new()),
+            flusher: Mutex::new(None),
+            reloader: Mutex::new(None),
+            lock_timeout_ms: AtomicU64::new(DEFAULT_LOCK_TIMEOUT.as_millis() as u64),
+            output_pretty: AtomicBool::new(false),
+            first_run,
+        }
+    }
+    
+    // Batches disk writes: with Some(interval), mutations are written by a
+    // background thread at most once per interval; None (the default) saves
+    // on every mutation. Pending changes are written when switching back to
+    // None, on flush(), and when the manager is dropped.
+    pub fn set_save_interval(&self, interval: Option<Duration>) -> Result<(), ClipboardError> {
+        let mut flusher = self.flusher.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(existing) = flusher.take() {
+            existing.stop();
+        }
+        
+        let flushed = {
+            let mut state = self.write_state()?;
+            state.defer_saves = interval.is_some();
+            state.flush()
+        };
+        
+        // The new interval applies even if writing the pending changes failed
+        if let Some(interval) = interval {
+            *flusher = Some(Flusher::spawn(Arc::clone(&self.state), interval));
+        }
+        flushed
+    }
+    
+    pub fn flush(&self) -> Result<(), ClipboardError> {
+        self.write_state()?.flush()
+    }
+    
+    // Starts watching the config file and reloads the state (firing the
+    // change callback with an empty name) when another process changes it.
+    // Unsaved deferred changes are lost on such a reload.
+    pub fn enable_auto_reload(&self) -> Result<(), ClipboardError> {
+        let mut reloader = self.reloader.lock().unwrap_or_else(PoisonError::into_inner);
+        if reloader.is_some() {
+            return Ok(());
+        }
+        
+        let path = {
+            let state = self.read_state()?;
+            if state.in_memory {
+                return Err(ClipboardError::InvalidArgument);
+            }
+            state.config_path().ok_or(ClipboardError::Io)?
+        };
+        let spawned = AutoReloader::spawn(Arc::clone(&self.state), Arc::clone(&self.observers), path)
+            .map_err(|_| ClipboardError::Io)?;
+        *reloader = Some(spawned);
+        Ok(())
+    }
+    
+    // Must not be called from a change callback fired by an auto-reload
+    pub fn disable_auto_reload(&self) {
+        let reloader = self.reloader.lock().unwrap_or_else(PoisonError::into_inner).take();
+        if let Some(reloader) = reloader {
+            reloader.stop();
+        }
+    }
+    
+    pub fn with_config_path(path: PathBuf) -> Result<Self, String> {
+        ClipboardManager::new(Some(path))
+    }
+    
+    // How long calls wait for another call holding the state to finish
+    // before failing with Busy, so a wedged call can't hang every caller.
+    // None waits indefinitely.
+    pub fn set_lock_timeout(&self, timeout: Option<Duration>) {
+        let ms = match timeout {
+            Some(timeout) => (timeout.as_millis() as u64).max(1),
+            None => 0
+        };
+        self.lock_timeout_ms.store(ms, Ordering::SeqCst);
+    }
+    
+    // Indent every JSON result for readable logs. Off by default, since the
+    // app only parses them. The config file is always pretty unless compact.
+    pub fn set_output_pretty(&self, pretty: bool) {
+        self.output_pretty.store(pretty, Ordering::SeqCst);
+    }
+    
+    fn to_json<T: Serialize + ?Sized>(&self, value: &T) -> serde_json::Result<String> {
+        if self.output_pretty.load(Ordering::SeqCst) {
+            serde_json::to_string_pretty(value)
+        } else {
+            serde_json::to_string(value)
+        }
+    }
+    
+    fn read_state(&self) -> Result<RwLockReadGuard<'_, ClipboardState>, ClipboardError> {
+        self.acquire(|| self.state.try_read())
+    }
+    
+    fn write_state(&self) -> Result<RwLockWriteGuard<'_, ClipboardState>, ClipboardError> {
+        self.acquire(|| self.state.try_write())
+    }
+    
+    // Retries `try_lock` until it succeeds or the lock timeout passes. A
+    // panic while a lock is held poisons it. Recover the guard instead of
+    // failing so one failed call doesn't break every later FFI call.
+    fn acquire<G>(&self, try_lock: impl Fn() -> TryLockResult<G>) -> Result<G, ClipboardError> {
+        let timeout_ms = self.lock_timeout_ms.load(Ordering::SeqCst);
+        let deadline = Instant::now() + Duration::from_millis(timeout_ms);
+        loop {
+            match try_lock() {
+                Ok(guard) => return Ok(guard),
+                Err(TryLockError::Poisoned(e)) => return Ok(e.into_inner()),
+                Err(TryLockError::WouldBlock) => {}
+            }
+            if timeout_ms != 0 && Instant::now() >= deadline {
+                return Err(ClipboardError::Busy);
+            }
+            thread::sleep(LOCK_RETRY_INTERVAL);
+        }
+    }
+    
+    pub fn set_callback(&self, func: Option<ChangeCallbackFn>, user_data: *mut c_void) {
+        let mut callback = self.observers.callback.lock().unwrap_or_else(PoisonError::into_inner);
+        *callback = func.map(|func| ChangeCallback { func, user_data });
+    }
+    
+    // Removes the global callback and every per-register one
+    pub fn clear_callbacks(&self) {
+        self.observers.clear();
+    }
+    
+    // Same as set_callback but only fires for changes to `name`, and for bulk
+    // changes (with an empty name). Dropped when the register is removed,
+    // renamed away, or merged into another.
+    pub fn set_callback_for(&self, name: &str, func: Option<ChangeCallbackFn>, user_data: *mut c_void) {
+        let mut callbacks = self.observers.register_callbacks.lock().unwrap_or_else(PoisonError::into_inner);
+        match func {
+            Some(func) => {
+                callbacks.insert(name_key(name).into_owned(), ChangeCallback { func, user_data });
+            },
+            None => {
+                callbacks.remove(&*name_key(name));
+            }
+        }
+    }
+    
+    // Runs `f` under the state lock and, if the change took effect (even if
+    // saving it failed), bumps the revision and fires the change callback for
+    // each name in `changed` after the lock is released so a callback may
+    // safely call back into the manager.
+    fn mutate<T, E: MutationError>(&self, changed: &[&str], f: impl FnOnce(&mut ClipboardState) -> Result<T, E>) -> Result<T, E> {
+        self.mutate_where(changed, f, took_effect)
+    }
+    
+    // Same as mutate for state methods that return Ok(false) when there was
+    // nothing to change, e.g. setting a register's content to what it
+    // already holds; those neither bump the revision nor notify
+    fn mutate_changed(&self, changed: &[&str], f: impl FnOnce(&mut ClipboardState) -> Result<bool, ClipboardError>) -> Result<(), ClipboardError> {
+        self.mutate_where(changed, f, |result| result != &Ok(false) && took_effect(result)).map(drop)
+    }
+    
+    fn mutate_where<T, E: MutationError>(&self, changed: &[&str], f: impl FnOnce(&mut ClipboardState) -> Result<T, E>, effect: impl FnOnce(&Result<T, E>) -> bool) -> Result<T, E> {
+        let (result, evicted) = {
+            let mut state = match self.write_state() {
+                Ok(state) => state,
+                Err(e) => return Err(E::from_error(e))
+            };
+            let result = f(&mut state);
+            (result, std::mem::take(&mut state.evicted))
+        };
+        
+        if effect(&result) {
+            self.observers.revision.fetch_add(1, Ordering::SeqCst);
+            // Report names as stored, not as the caller spelled them
+            for name in changed {
+                self.notify(&name_key(name.trim()));
+            }
+        }
+        for name in &evicted {
+            self.notify(name);
+            self.drop_register_callback(name);
+        }
+        result
+    }
+    
+    // Internal if the state lock is poisoned by an earlier panic, otherwise
+    // the error from the last config write if it failed (see save_error), or
+    // Io if the config directory is not writable
+    pub fn health_check(&self) -> Result<(), ClipboardError> {
+        if self.state.is_poisoned() {
+            return Err(ClipboardError::Internal);
+        }
+        let state = self.read_state()?;
+        if let Some(error) = state.save_error {
+            return Err(error);
+        }
+        state.check_writable()
+    }
+    
+    pub fn revision(&self) -> u64 {
+        self.observers.revision.load(Ordering::SeqCst)
+    }
+    
+    fn notify(&self, name: &str) {
+        self.observers.notify(name);
+    }
+    
+    fn drop_register_callback(&self, name: &str) {
+        self.observers.drop_register_callback(name);
+    }
+    
+    // Core functions that will be exposed to Swift
+    pub fn add_register(&self, name: &str, shortcut: &str) -> Result<(), ClipboardError> {
+        self.mutate(&[name], |state| state.add_register(name.to_string(), shortcut.to_string()))
+    }
+    
+    pub fn add_register_unique(&self, base_name: &str, shortcut: &str) -> Result<String, ClipboardError> {
+        let result = self.mutate(&[], |state| state.add_register_unique(base_name, shortcut));
+        if let Ok(name) = &result {
+            self.notify(name);
+        }
+        result
+    }
+    
+    pub fn add_registers(&self, entries: Vec<(String, String, String)>) -> Result<usize, ClipboardError> {
+        self.mutate(&[""], |state| state.add_registers(entries))
+    }
+    
+    pub fn set_register(&self, name: &str, content: &str, shortcut: &str) -> Result<SetOutcome, ClipboardError> {
+        self.mutate(&[name], |state| state.set_register(name, content.to_string(), shortcut.to_string()))
+    }
+    
+    pub fn add_register_checked(&self, name: &str, shortcut: &str) -> Result<(), ClipboardError> {
+        self.mutate(&[name], |state| state.add_register_checked(name.to_string(), shortcut.to_string()))
+    }
+    
+    pub fn add_register_validated(&self, name: &str, shortcut: &str) -> Result<String, ClipboardError> {
+        self.mutate(&[name], |state| state.add_register_validated(name.to_string(), shortcut))
+    }
+    
+    pub fn update_shortcut_validated(&self, name: &str, shortcut: &str) -> Result<String, ClipboardError> {
+        self.mutate(&[name], |state| state.update_shortcut_validated(name, shortcut))
+    }
+    
+    pub fn shortcut_in_use(&self, shortcut: &str) -> Option<String> {
+        let state = self.read_state().ok()?;
+        state.shortcut_in_use(shortcut)
+    }
+    
+    pub fn update_register_content(&self, name: &str, content: &str) -> Result<(), ClipboardError> {
+        self.mutate_changed(&[name], |state| state.update_register_content(name, content.to_string()))
+    }
+    
+    pub fn update_register_content_cas(&self, name: &str, expected_hash: &str, content: &str) -> Result<(), ClipboardError> {
+        self.mutate_changed(&[name], |state| state.update_register_content_cas(name, expected_hash, content.to_string()))
+    }
+    
+    pub fn update_register_content_raw(&self, name: &str, content: &str) -> Result<(), ClipboardError> {
+        self.mutate_changed(&[name], |state| state.update_register_content_raw(name, content.to_string()))
+    }
+    
+    pub fn append_register_content(&self, name: &str, content: &str, separator: Option<&str>) -> Result<(), ClipboardError> {
+        self.mutate_changed(&[name], |state| state.append_register_content(name, content, separator))
+    }
+    
+    pub fn replace_register_content(&self, name: &str, content: &str) -> Result<String, ClipboardError> {
+        self.mutate(&[name], |state| state.replace_register_content(name, content.to_string()))
+    }
+    
+    pub fn clear_register_content(&self, name: &str) -> Result<(), ClipboardError> {
+        self.mutate_changed(&[name], |state| state.clear_register_content(name))
+    }
+    
+    pub fn clear_all_contents(&self) -> Result<(), ClipboardError> {
+        self.mutate(&[""], |state| state.clear_all_contents())
+    }
+    
+    pub fn get_register_content(&self, name: &str) -> Option<String> {
+        let state = self.read_state().ok()?;
+        state.get_register_content(name)
+    }
+    
+    pub fn get_register_content_expanded(&self, name: &str) -> Option<String> {
+        self.read_state().ok()?.get_register_content_expanded(name)
+    }
+    
+    pub fn get_register_content_rendered(&self, name: &str) -> Option<String> {
+        self.read_state().ok()?.get_register_content_rendered(name)
+    }
+    
+    pub fn get_register_content_with_vars(&self, name: &str, vars: &HashMap<String, String>) -> Option<String> {
+        self.read_state().ok()?.get_register_content_with_vars(name, vars)
+    }
+    
+    pub fn get_register(&self, name: &str) -> Option<String> {
+        let state = self.read_state().ok()?;
+        let register = state.get_register(name)?;
+        
+        self.to_json(&register).ok()
+    }
+    
+    // The register as JSON, as get_register returns it
+    pub fn get_or_create(&self, name: &str, default_shortcut: &str) -> Result<String, ClipboardError> {
+        // Don't bump the revision or notify when nothing is created
+        if let Some(json) = self.get_register(name) {
+            return Ok(json);
+        }
+        
+        let register = self.mutate(&[name], |state| state.get_or_create(name, default_shortcut))?;
+        self.to_json(&register).map_err(|_| ClipboardError::Internal)
+    }
+    
+    pub fn update_register_binary(&self, name: &str, mime: &str, data: &[u8]) -> Result<(), ClipboardError> {
+        self.mutate(&[name], |state| state.update_register_binary(name, mime, data))
+    }
+    
+    pub fn get_register_binary(&self, name: &str) -> Option<Vec<u8>> {
+        let state = self.read_state().ok()?;
+        state.get_register_binary(name)
+    }
+    
+    pub fn get_register_history(&self, name: &str) -> Option<String> {
+        let state = self.read_state().ok()?;
+        let history = state.get_register_history(name)?;
+        
+        self.to_json(&history).ok()
+    }
+    
+    pub fn undo_register_content(&self, name: &str) -> Result<(), ClipboardError> {
+        self.mutate(&[name], |state| state.undo_register_content(name))
+    }
+    
+    pub fn remove_register(&self, name: &str) -> Result<(), ClipboardError> {
+        let result = self.mutate(&[name], |state| state.remove_register(name));
+        if took_effect(&result) {
+            self.drop_register_callback(name);
+        }
+        result
+    }
+    
+    pub fn rename_register(&self, old_name: &str, new_name: &str) -> Result<(), ClipboardError> {
+        let result = self.mutate(&[old_name, new_name], |state| state.rename_register(old_name, new_name));
+        if took_effect(&result) && name_key(old_name) != name_key(new_name.trim()) {
+            self.drop_register_callback(old_name);
+        }
+        result
+    }
+    
+    pub fn duplicate_register(&self, src: &str, new_name: &str) -> Result<(), ClipboardError> {
+        self.mutate(&[new_name], |state| state.duplicate_register(src, new_name))
+    }
+    
+    pub fn merge_registers(&self, target: &str, source: &str, separator: &str) -> Result<(), ClipboardError> {
+        let result = self.mutate(&[target, source], |state| state.merge_registers(target, source, separator));
+        if took_effect(&result) {
+            self.drop_register_callback(source);
+        }
+        result
+    }
+    
+    pub fn swap_contents(&self, a: &str, b: &str) -> Result<(), ClipboardError> {
+        self.mutate(&[a, b], |state| state.swap_contents(a, b))
+    }
+    
+    pub fn update_shortcut(&self, name: &str, shortcut: &str) -> Result<(), ClipboardError> {
+        self.mutate(&[name], |state| state.update_shortcut(name, shortcut.to_string()))
+    }
+    
+    pub fn update_register(&self, name: &str, content: Option<&str>, shortcut: Option<&str>) -> Result<(), ClipboardError> {
+        self.mutate(&[name], |state| state.update_register(name, content.map(str::to_string), shortcut.map(str::to_string)))
+    }
+    
+    pub fn update_shortcut_checked(&self, name: &str, shortcut: &str) -> Result<(), ClipboardError> {
+        self.mutate(&[name], |state| state.update_shortcut_checked(name, shortcut.to_string()))
+    }
+    
+    pub fn set_max_registers(&self, max: Option<usize>) -> Result<(), ClipboardError> {
+        self.mutate(&[], |state| state.set_max_registers(max))
+    }
+    
+    pub fn set_normalize_on_store(&self, normalize: bool) -> Result<(), ClipboardError> {
+        self.mutate(&[], |state| state.set_normalize_on_store(normalize))
+    }
+    
+    pub fn set_max_content_bytes(&self, max: Option<usize>) -> Result<(), ClipboardError> {
+        self.mutate(&[], |state| state.set_max_content_bytes(max))
+    }
+    
+    pub fn search(&self, query: &str, include_hidden: bool) -> Option<String> {
+        self.search_with_mode(query, SearchMode::Substring, include_hidden)
+    }
+    
+    pub fn search_with_mode(&self, query: &str, mode: SearchMode, include_hidden: bool) -> Option<String> {
+        let state = self.read_state().ok()?;
+        let results = state.search_with_mode(query, mode, include_hidden);
+        
+        let json = match self.to_json(&results) {
+            Ok(json) => json,
+            Err(_) => "[]".to_string()
+        };
+        Some(json)
+    }
+    
+    // [[name, register, distance], ...], best match first
+    pub fn search_fuzzy(&self, query: &str, max_distance: usize, include_hidden: bool) -> Option<String> {
+        let state = self.read_state().ok()?;
+        let results = state.search_fuzzy(query, max_distance, include_hidden);
+        
+        let json = match self.to_json(&results) {
+            Ok(json) => json,
+            Err(_) => "[]".to_string()
+        };
+        Some(json)
+    }
+    
+    pub fn add_tag(&self, name: &str, tag: &str) -> Result<(), ClipboardError> {
+        self.mutate(&[name], |state| state.add_tag(name, tag))
+    }
+    
+    pub fn remove_tag(&self, name: &str, tag: &str) -> Result<(), ClipboardError> {
+        self.mutate(&[name], |state| state.remove_tag(name, tag))
+    }
+    
+    pub fn get_registers_by_tag(&self, tag: &str, include_hidden: bool) -> Option<String> {
+        let state = self.read_state().ok()?;
+        let results = state.get_registers_by_tag(tag, include_hidden);
+        
+        let json = match self.to_json(&results) {
+            Ok(json) => json,
+            Err(_) => "[]".to_string()
+        };
+        Some(json)
+    }
+    
+    pub fn filter(&self, tag: Option<&str>, format: Option<&str>, include_hidden: bool) -> Option<String> {
+        let state = self.read_state().ok()?;
+        let registers = state.filter(tag, format, include_hidden);
+        
+        let json = match self.to_json(&registers) {
+            Ok(json) => json,
+            Err(_) => "[]".to_string()
+        };
+        Some(json)
+    }
+    
+    // Histories may be trimmed, so listeners get a bulk notification
+    pub fn set_history_limit(&self, limit: usize) -> Result<(), ClipboardError> {
+        self.mutate(&[""], |state| state.set_history_limit(limit))
+    }
+    
+    pub fn move_register(&self, name: &str, new_index: usize) -> Result<(), ClipboardError> {
+        self.mutate(&[name], |state| state.move_register(name, new_index))
+    }
+    
+    pub fn promote(&self, name: &str) -> Result<(), ClipboardError> {
+        self.mutate(&[name], |state| state.promote(name))
+    }
+    
+    pub fn register_count(&self) -> Result<usize, ClipboardError> {
+        Ok(self.read_state()?.len())
+    }
+    
+    pub fn is_empty(&self) -> Result<bool, ClipboardError> {
+        Ok(self.read_state()?.is_empty())
+    }
+    
+    pub fn total_content_bytes(&self) -> Result<usize, ClipboardError> {
+        Ok(self.read_state()?.total_content_bytes())
+    }
+    
+    pub fn estimated_file_size(&self) -> Result<usize, ClipboardError> {
+        Ok(self.read_state()?.estimated_file_size())
+    }
+    
+    // Replaces the clock behind every timestamp, e.g. with a ManualClock in
+    // tests
+    pub fn set_clock(&self, clock: Arc<dyn Clock>) -> Result<(), ClipboardError> {
+        self.write_state()?.set_clock(clock);
+        Ok(())
+    }
+    
+    // True if there was no config file when the manager was created, e.g. to
+    // decide whether to show onboarding. Doesn't need the state lock.
+    pub fn is_first_run(&self) -> bool {
+        self.first_run
+    }
+    
+    pub fn contains_register(&self, name: &str) -> Result<bool, ClipboardError> {
+        Ok(self.read_state()?.contains_register(name))
+    }
+    
+    pub fn register_names(&self, include_hidden: bool) -> Option<String> {
+        let names = self.read_state().ok()?.register_names(include_hidden);
+        
+        let json = match self.to_json(&names) {
+            Ok(json) => json,
+            Err(_) => "[]".to_string()
+        };
+        Some(json)
+    }
+    
+    pub fn mark_used(&self, name: &str) -> Result<(), ClipboardError> {
+        self.mutate(&[name], |state| state.mark_used(name))
+    }
+    
+    pub fn get_recently_used(&self, limit: usize, include_hidden: bool) -> Option<String> {
+        let state = self.read_state().ok()?;
+        let registers = state.get_recently_used(limit, include_hidden);
+        
+        let json = match self.to_json(&registers) {
+            Ok(json) => json,
+            Err(_) => "[]".to_string()
+        };
+        Some(json)
+    }
+    
+    pub fn get_use_count(&self, name: &str) -> Option<u64> {
+        self.read_state().ok()?.get_use_count(name)
+    }
+    
+    pub fn get_most_used(&self, limit: usize, include_hidden: bool) -> Option<String> {
+        let state = self.read_state().ok()?;
+        let registers = state.get_most_used(limit, include_hidden);
+        
+        let json = match self.to_json(&registers) {
+            Ok(json) => json,
+            Err(_) => "[]".to_string()
+        };
+        Some(json)
+    }
+    
+    pub fn set_locked(&self, name: &str, locked: bool) -> Result<(), ClipboardError> {
+        self.mutate(&[name], |state| state.set_locked(name, locked))
+    }
+    
+    pub fn set_hidden(&self, name: &str, hidden: bool) -> Result<(), ClipboardError> {
+        self.mutate(&[name], |state| state.set_hidden(name, hidden))
+    }
+    
+    pub fn set_pinned(&self, name: &str, pinned: bool) -> Result<(), ClipboardError> {
+        self.mutate(&[name], |state| state.set_pinned(name, pinned))
+    }
+    
+    pub fn get_all_registers(&self) -> Option<String> {
+        let state = self.read_state().ok()?;
+        let mut registers = Vec::new();
+        state.for_each_register(|name, register| registers.push((name, register)));
+        Some(self.registers_json(registers))
+    }
+    
+    // Runs `f` on every listed register under the read lock, so `f` must not
+    // call back into the manager for writing
+    pub fn for_each_register<F: FnMut(&str, &ClipboardRegister)>(&self, f: F) -> Result<(), ClipboardError> {
+        self.read_state()?.for_each_register(f);
+        Ok(())
+    }
+    
+    // Like get_all_registers, but each register's content is cut to
+    // `max_chars` and it carries a "truncated" flag instead of a hash
+    pub fn get_all_registers_preview(&self, max_chars: usize) -> Option<String> {
+        let previews = self.read_state().ok()?.get_all_registers_preview(max_chars);
+        
+        let json = match self.to_json(&previews) {
+            Ok(json) => json,
+            Err(_) => "[]".to_string()
+        };
+        Some(json)
+    }
+    
+    pub fn get_all_registers_including_hidden(&self) -> Option<String> {
+        let registers = self.read_state().ok()?.get_all_registers_including_hidden();
+        Some(self.registers_json(registers.iter().map(|(name, register)| (name.as_str(), register))))
+    }
+    
+    // [[name, register], ...] with each register's content_hash added
+    fn registers_json<'a>(&self, registers: impl IntoIterator<Item = (&'a str, &'a ClipboardRegister)>) -> String {
+        let hashed: Vec<(&str, HashedRegister)> = registers.into_iter()
+            .map(|(name, register)| (name, HashedRegister::new(register)))
+            .collect();
+        
+        match self.to_json(&hashed) {
+            Ok(json) => json,
+            Err(_) => "[]".to_string()
+        }
+    }
+    
+    // Same registers and order as get_all_registers, as an array of flat
+    // objects instead of [name, register] pairs
+    pub fn get_all_registers_v2(&self) -> Option<String> {
+        let state = self.read_state().ok()?;
+        let mut named = Vec::new();
+        state.for_each_register(|name, register| named.push(NamedRegister { name, register: HashedRegister::new(register) }));
+        
+        let json = match self.to_json(&named) {
+            Ok(json) => json,
+            Err(_) => "[]".to_string()
+        };
+        Some(json)
+    }
+    
+    pub fn export_to(&self, path: &Path) -> Result<(), ClipboardError> {
+        self.read_state()?.export_to(path)
+    }
+    
+    pub fn export_jsonl(&self, path: &Path) -> Result<(), ClipboardError> {
+        let state = self.read_state()?;
+        let file = fs::File::create(path).map_err(|_| ClipboardError::Io)?;
+        
+        state.export_jsonl(&mut BufWriter::new(file)).map_err(|_| ClipboardError::Io)
+    }
+    
+    pub fn import_jsonl(&self, path: &Path, replace: bool) -> Result<usize, ImportError> {
+        let file = fs::File::open(path).map_err(|_| ClipboardError::Io)?;
+        
+        self.mutate(&[""], |state| state.import_jsonl(BufReader::new(file), replace))
+    }
+    
+    pub fn import_from(&self, path: &Path, replace: bool, overwrite: bool) -> Result<(), ImportError> {
+        self.mutate(&[""], |state| state.import_from(path, replace, overwrite))
+    }
+    
+    pub fn import_from_with(&self, path: &Path, replace: bool, on_conflict: OnConflict) -> Result<(), ImportError> {
+        self.mutate(&[""], |state| state.import_from_with(path, replace, on_conflict))
+    }
+    
+    pub fn export_csv(&self) -> Option<String> {
+        let state = self.read_state().ok()?;
+        Some(state.export_csv())
+    }
+    
+    pub fn export_plist(&self) -> Result<String, ClipboardError> {
+        self.read_state()?.export_plist()
+    }
+    
+    pub fn import_plist(&self, data: &str, replace: bool) -> Result<usize, ImportError> {
+        self.mutate(&[""], |state| state.import_plist(data, replace))
+    }
+    
+    pub fn import_csv(&self, data: &str, replace: bool) -> Result<usize, ImportError> {
+        self.mutate(&[""], |state| state.import_csv(data, replace))
+    }
+    
+    pub fn import_csv_with(&self, data: &str, replace: bool, on_conflict: OnConflict) -> Result<usize, ImportError> {
+        self.mutate(&[""], |state| state.import_csv_with(data, replace, on_conflict))
+    }
+    
+    pub fn active_profile(&self) -> Option<String> {
+        Some(self.read_state().ok()?.active_profile().to_string())
+    }
+    
+    pub fn list_profiles(&self) -> Option<String> {
+        let names = self.read_state().ok()?.list_profiles();
+        
+        let json = match self.to_json(&names) {
+            Ok(json) => json,
+            Err(_) => "[]".to_string()
+        };
+        Some(json)
+    }
+    
+    pub fn create_profile(&self, name: &str) -> Result<(), ClipboardError> {
+        self.mutate(&[], |state| state.create_profile(name))
+    }
+    
+    // Every register may have changed, so listeners get a bulk notification
+    pub fn switch_profile(&self, name: &str) -> Result<(), ClipboardError> {
+        self.mutate(&[""], |state| state.switch_profile(name))
+    }
+    
+    pub fn delete_profile(&self, name: &str) -> Result<(), ClipboardError> {
+        self.mutate(&[], |state| state.delete_profile(name))
+    }
+    
+    // JSON array of issue descriptions; empty when the state is valid
+    pub fn validate(&self) -> Option<String> {
+        let issues = self.read_state().ok()?.validate();
+        
+        let json = match self.to_json(&issues) {
+            Ok(json) => json,
+            Err(_) => "[]".to_string()
+        };
+        Some(json)
+    }
+    
+    pub fn get_all_registers_sorted(&self, sort: SortOrder) -> Option<String> {
+        let registers = self.read_state().ok()?.get_all_registers_sorted(sort);
+        Some(self.registers_json(registers.iter().map(|(name, register)| (name.as_str(), register))))
+    }
+    
+    // Same shape as get_registers_page
+    pub fn get_registers_page_sorted(&self, offset: usize, limit: usize, sort: SortOrder) -> Option<String> {
+        let state = self.read_state().ok()?;
+        let json = serde_json::json!({
+            "total": state.visible_len(),
+            "registers": state.get_registers_page_sorted(offset, limit, sort),
+        });
+        self.to_json(&json).ok()
+    }
+    
+    // {"total": N, "registers": [...]}, where total counts every listed
+    // (not hidden) register so callers know how many pages there are
+    pub fn get_registers_page(&self, offset: usize, limit: usize) -> Option<String> {
+        let state = self.read_state().ok()?;
+        let json = serde_json::json!({
+            "total": state.visible_len(),
+            "registers": state.get_registers_page(offset, limit),
+        });
+        self.to_json(&json).ok()
+    }
+    
+    pub fn config_path(&self) -> Option<PathBuf> {
+        self.read_state().ok()?.config_path()
+    }
+    
+    pub fn get_shortcut(&self, name: &str) -> Option<String> {
+        self.read_state().ok()?.get_shortcut(name)
+    }
+    
+    // {"name": "shortcut", ...}
+    pub fn shortcut_map(&self) -> Option<String> {
+        let shortcuts = self.read_state().ok()?.shortcut_map();
+        
+        self.to_json(&shortcuts).ok()
+    }
+    
+    pub fn find_by_shortcut(&self, shortcut: &str) -> Option<String> {
+        let found = self.read_state().ok()?.find_by_shortcut(shortcut)?;
+        
+        self.to_json(&found).ok()
+    }
+    
+    pub fn set_compression(&self, compress: bool) -> Result<(), ClipboardError> {
+        self.mutate(&[], |state| state.set_compression(compress))
+    }
+    
+    pub fn set_compact(&self, compact: bool) -> Result<(), ClipboardError> {
+        self.mutate(&[], |state| state.set_compact(compact))
+    }
+    
+    // Histories may be trimmed, as for set_history_limit
+    pub fn compact(&self) -> Result<(), ClipboardError> {
+        self.mutate(&[""], |state| state.compact())
+    }
+    
+    pub fn get_trash(&self) -> Option<String> {
+        let trash = self.read_state().ok()?.get_trash();
+        
+        let json = match self.to_json(&trash) {
+            Ok(json) => json,
+            Err(_) => "[]".to_string()
+        };
+        Some(json)
+    }
+    
+    pub fn restore_from_trash(&self, name: &str) -> Result<(), ClipboardError> {
+        self.mutate(&[name], |state| state.restore_from_trash(name))
+    }
+    
+    pub fn empty_trash(&self) -> Result<(), ClipboardError> {
+        self.mutate(&[], |state| state.empty_trash())
+    }
+    
+    pub fn diff_content(&self, name: &str, other: &str) -> Option<String> {
+        let diff = self.read_state().ok()?.diff_content(name, other)?;
+        
+        self.to_json(&diff).ok()
+    }
+    
+    // For picking up external edits to the config. Unsaved deferred changes
+    // are discarded.
+    pub fn reload(&self) -> Result<(), ClipboardError> {
+        self.mutate(&[""], |state| state.reload())
+    }
+    
+    pub fn set_format(&self, name: &str, format: &str) -> Result<(), ClipboardError> {
+        self.mutate(&[name], |state| state.set_format(name, format))
+    }
+    
+    // {"valid": true} or {"valid": false, "error": "..."}; None if the
+    // register does not exist
+    pub fn validate_register(&self, name: &str) -> Option<String> {
+        let state = self.read_state().ok()?;
+        state.get_register(name)?;
+        
+        let json = match state.validate_register(name) {
+            Ok(()) => serde_json::json!({ "valid": true }),
+            Err(e) => serde_json::json!({ "valid": false, "error": e })
+        };
+        self.to_json(&json).ok()
+    }
+    
+    pub fn move_register_to_profile(&self, name: &str, target_profile: &str) -> Result<(), ClipboardError> {
+        let result = self.mutate(&[name], |state| state.move_register_to_profile(name, target_profile));
+        if took_effect(&result) {
+            self.drop_register_callback(name);
+        }
+        result
+    }
+    
+    pub fn set_backup_count(&self, count: usize) -> Result<(), ClipboardError> {
+        self.mutate(&[], |state| state.set_backup_count(count))
+    }
+    
+    // [{"index": 1, "path": "..."}, ...], newest first
+    pub fn list_backups(&self) -> Option<String> {
+        let backups: Vec<serde_json::Value> = self.read_state().ok()?.list_backups().into_iter()
+            .map(|(index, path)| serde_json::json!({
+                "index": index,
+                "path": path.to_string_lossy()
+            }))
+            .collect();
+        
+        let json = match self.to_json(&backups) {
+            Ok(json) => json,
+            Err(_) => "[]".to_string()
+        };
+        Some(json)
+    }
+    
+    // See ClipboardState::reset. Per-register callbacks are dropped along
+    // with the registers.
+    pub fn reset(&self, backup: bool) -> Result<(), ClipboardError> {
+        let result = self.mutate(&[""], |state| state.reset(backup));
+        if result.is_ok() {
+            self.observers.register_callbacks.lock().unwrap_or_else(PoisonError::into_inner).clear();
+        }
+        result
+    }
+    
+    pub fn restore_backup(&self, index: usize) -> Result<(), ClipboardError> {
+        self.mutate(&[""], |state| state.restore_backup(index))
+    }
+    
+    pub fn get_register_hash(&self, name: &str) -> Option<String> {
+        self.read_state().ok()?.get_register_hash(name)
+    }
+    
+    pub fn set_register_ttl(&self, name: &str, seconds: Option<u64>) -> Result<(), ClipboardError> {
+        self.mutate(&[name], |state| state.set_register_ttl(name, seconds))
+    }
+    
+    // Purged registers are notified and lose their callbacks like evicted ones
+    pub fn purge_expired(&self) -> Result<usize, ClipboardError> {
+        self.mutate(&[], |state| state.purge_expired())
+    }
+    
+    // A point-in-time copy of the whole state, for restore_snapshot
+    pub fn snapshot(&self) -> Result<ClipboardState, ClipboardError> {
+        Ok(self.read_state()?.clone())
+    }
+    
+    pub fn restore_snapshot(&self, snapshot: ClipboardState) -> Result<(), ClipboardError> {
+        self.mutate(&[""], |state| state.restore_snapshot(snapshot))
+    }
+    
+    // The snapshot in the config file format
+    pub fn snapshot_json(&self) -> Option<String> {
+        let state = self.read_state().ok()?;
+        self.to_json(&ConfigFile { version: CONFIG_VERSION, state: &state }).ok()
+    }
+    
+    // Accepts what snapshot_json returned, or any config file
+    pub fn restore_snapshot_json(&self, json: &str) -> Result<(), ClipboardError> {
+        let snapshot = ClipboardState::from_config_json(json.as_bytes()).map_err(|_| ClipboardError::InvalidFormat)?;
+        self.restore_snapshot(snapshot)
+    }
+    
+    pub fn apply_batch(&self, ops: &[BatchOp]) -> Result<(), BatchError> {
+        let names: Vec<&str> = ops.iter().flat_map(BatchOp::names).collect();
+        let result = self.mutate(&names, |state| state.apply_batch(ops));
+        if took_effect(&result) {
+            for name in ops.iter().filter_map(BatchOp::removed_name) {
+                self.drop_register_callback(name);
+            }
+        }
+        result
+    }
+    
+    // Takes a JSON array of ops and reports
+    // {"ok": bool, "error": code, "message": "...", "results": [...]}, with
+    // "error" and "message" only when not ok and one result per op:
+    // "applied", "failed" (the op that stopped the batch), "rolled_back"
+    // (applied, then undone) or "skipped" (never run). A failed save keeps
+    // every op applied in memory, like any other SaveFailed. JSON that is not
+    // an array of ops gets InvalidFormat, with the parse error as "message".
+    pub fn apply_batch_json(&self, json: &str) -> Option<String> {
+        let ops: Vec<BatchOp> = match serde_json::from_str(json) {
+            Ok(ops) => ops,
+            Err(e) => {
+                let mut report = batch_report(0, Err(BatchError { index: None, error: ClipboardError::InvalidFormat }));
+                report["message"] = format!("invalid batch: {}", e).into();
+                return self.to_json(&report).ok();
+            }
+        };
+        
+        let result = self.apply_batch(&ops);
+        self.to_json(&batch_report(ops.len(), result)).ok()
+    }
+    
+    // {"chars": .., "bytes": .., "words": .., "lines": ..}
+    pub fn register_stats(&self, name: &str) -> Option<String> {
+        let stats = self.read_state().ok()?.register_stats(name)?;
+        
+        self.to_json(&stats).ok()
+    }
+    
+    // Removes unlocked registers with no content
+    pub fn remove_empty(&self) -> Result<usize, ClipboardError> {
+        self.mutate(&[], |state| state.remove_where(|_, register| register.content.is_empty()))
+    }
+    
+    // Removes unlocked registers tagged `tag`
+    pub fn remove_by_tag(&self, tag: &str) -> Result<usize, ClipboardError> {
+        self.mutate(&[], |state| state.remove_where(|_, register| register.tags.iter().any(|t| t == tag)))
+    }
+    
+    // [["a", "b"], ...]: groups of registers with identical content
+    pub fn find_duplicates(&self) -> Option<String> {
+        let groups = self.read_state().ok()?.find_duplicates();
+        
+        self.to_json(&groups).ok()
+    }
+    
+    pub fn dedupe(&self, keep: DedupeKeep) -> Result<usize, ClipboardError> {
+        self.mutate(&[], |state| state.dedupe(keep))
+    }
+}
+
+impl Drop for ClipboardManager {
+    fn drop(&mut self) {
+        let reloader = self.reloader.get_mut().unwrap_or_else(PoisonError::into_inner);
+        if let Some(reloader) = reloader.take() {
+            reloader.stop();
+        }
+        
+        let flusher = self.flusher.get_mut().unwrap_or_else(PoisonError::into_inner);
+        if let Some(flusher) = flusher.take() {
+            flusher.stop();
+        }
+        
+        // Nothing else can be holding the state by now, so just wait. There
+        // is no one left to report a failed save to.
+        let mut state = self.state.write().unwrap_or_else(PoisonError::into_inner);
+        let _ = state.flush();
+    }
+}
+
+// C-compatible FFI functions to expose to Swift
+use std::os::raw::{c_char, c_int, c_void};
+use std::ffi::{CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+
+// Managers handed out by ffi_new and not yet destroyed, keyed by address,
+// each with the number of FFI calls currently using it. Destroy removes the
+// entry first, so new calls fail with Destroyed, then waits for the count to
+// drop to zero before freeing.
+fn live_managers() -> &'static Mutex<HashMap<usize, Arc<AtomicUsize>>> {
+    static LIVE: OnceLock<Mutex<HashMap<usize, Arc<AtomicUsize>>>> = OnceLock::new();
+    LIVE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+// A manager borrowed for the length of one FFI call; it keeps
+// clipboard_manager_destroy from freeing the manager until dropped
+struct ManagerRef<'a> {
+    manager: &'a ClipboardManager,
+    calls: Arc<AtomicUsize>,
+}
+
+impl std::ops::Deref for ManagerRef<'_> {
+    type Target = ClipboardManager;
+    
+    fn deref(&self) -> &ClipboardManager {
+        self.manager
+    }
+}
+
+impl Drop for ManagerRef<'_> {
+    fn drop(&mut self) {
+        self.calls.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// Helpers shared by the FFI functions below. They turn raw arguments into
+// Rust references, reporting null or non-UTF-8 input as a ClipboardError. A
+// pointer that was never returned by a constructor, or was already
+// destroyed, is Destroyed rather than being dereferenced.
+unsafe fn manager_ref<'a>(manager: *mut ClipboardManager) -> Result<ManagerRef<'a>, ClipboardError> {
+    if manager.is_null() {
+        return Err(ClipboardError::NullPointer);
+    }
+    
+    let live = live_managers().lock().unwrap_or_else(PoisonError::into_inner);
+    let calls = live.get(&(manager as usize)).ok_or(ClipboardError::Destroyed)?;
+    calls.fetch_add(1, Ordering::SeqCst);
+    Ok(ManagerRef { manager: &*manager, calls: Arc::clone(calls) })
+}
+
+// Every string parameter goes through here. Invalid UTF-8 is never coerced
+// to "": functions returning a status report it as InvalidUtf8, and those
+// returning a pointer return null.
+unsafe fn str_arg<'a>(s: *const c_char) -> Result<&'a str, ClipboardError> {
+    if s.is_null() {
+        return Err(ClipboardError::NullPointer);
+    }
+    CStr::from_ptr(s).to_str().map_err(|_| ClipboardError::InvalidUtf8)
+}
+
+fn into_c_string(s: String) -> *mut c_char {
+    match CString::new(s) {
+        Ok(c_str) => c_str.into_raw(),
+        Err(_) => std::ptr::null_mut()
+    }
+}
+
+// The ffi_* wrappers catch any panic so it never unwinds into Swift; a
+// caught panic is reported as ClipboardError::Internal (or null).
+fn ffi_int<F: FnOnce() -> Result<c_int, ClipboardError>>(f: F) -> c_int {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => value,
+        Ok(Err(e)) => e as c_int,
+        Err(_) => ClipboardError::Internal as c_int,
+    }
+}
+
+// Counts returned through ffi_int saturate at c_int::MAX rather than wrap
+// into the negative error range
+fn count_c_int(count: usize) -> c_int {
+    c_int::try_from(count).unwrap_or(c_int::MAX)
+}
+
+// Writes an ImportError's line number to `out_line` unless it is null; 0
+// stands for no line
+unsafe fn set_out_line(out_line: *mut c_int, line: Option<usize>) {
+    if !out_line.is_null() {
+        *out_line = line.map_or(0, count_c_int);
+    }
+}
+
+unsafe fn import_count(result: Result<usize, ImportError>, out_line: *mut c_int) -> Result<c_int, ClipboardError> {
+    result.map(count_c_int).map_err(|e| {
+        set_out_line(out_line, e.line);
+        e.error
+    })
+}
+
+// For values that may not fit in a c_int
+fn ffi_i64<F: FnOnce() -> Result<i64, ClipboardError>>(f: F) -> i64 {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => value,
+        Ok(Err(e)) => e as i64,
+        Err(_) => ClipboardError::Internal as i64,
+    }
+}
+
+fn ffi_status<F: FnOnce() -> Result<(), ClipboardError>>(f: F) -> c_int {
+    ffi_int(|| f().map(|()| CLIPBOARD_OK))
+}
+
+fn ffi_string<F: FnOnce() -> Option<String>>(f: F) -> *mut c_char {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Some(s)) => into_c_string(s),
+        _ => std::ptr::null_mut()
+    }
+}
+
+// Hands a byte buffer to the caller, who frees it with
+// clipboard_manager_free_bytes. Writes its length to `out_len` (0 on
+// failure); returns null on failure or if `out_len` is null.
+fn ffi_bytes<F: FnOnce() -> Option<Vec<u8>>>(out_len: *mut usize, f: F) -> *mut u8 {
+    if out_len.is_null() {
+        return std::ptr::null_mut();
+    }
+    unsafe { *out_len = 0; }
+    
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Some(bytes)) => {
+            let bytes = bytes.into_boxed_slice();
+            unsafe { *out_len = bytes.len(); }
+            Box::into_raw(bytes) as *mut u8
+        },
+        _ => std::ptr::null_mut()
+    }
+}
+
+fn ffi_new<F: FnOnce() -> Option<ClipboardManager>>(f: F) -> *mut ClipboardManager {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Some(manager)) => {
+            let manager = Box::into_raw(Box::new(manager));
+            let mut live = live_managers().lock().unwrap_or_else(PoisonError::into_inner);
+            live.insert(manager as usize, Arc::new(AtomicUsize::new(0)));
+            manager
+        },
+        _ => std::ptr::null_mut()
+    }
+}
+
+// Returns null if the config exists but can't be loaded; it is left as is
+#[no_mangle]
+pub extern "C" fn clipboard_manager_new() -> *mut ClipboardManager {
+    ffi_new(|| match ClipboardManager::new(None) {
+        Ok(manager) => Some(manager),
+        Err(e) => {
+            eprintln!("Failed to load config: {}", e);
+            None
+        }
+    })
+}
+
+// A manager that keeps registers in memory only and never touches disk
+#[no_mangle]
+pub extern "C" fn clipboard_manager_new_in_memory() -> *mut ClipboardManager {
+    ffi_new(|| Some(ClipboardManager::new_in_memory()))
+}
+
+// Returns null if path is null or not valid UTF-8, or if the config there
+// can't be loaded
+#[no_mangle]
+pub extern "C" fn clipboard_manager_new_with_path(path: *const c_char) -> *mut ClipboardManager {
+    ffi_new(|| {
+        let path = unsafe { str_arg(path).ok()? };
+        match ClipboardManager::with_config_path(PathBuf::from(path)) {
+            Ok(manager) => Some(manager),
+            Err(e) => {
+                eprintln!("Failed to load config: {}", e);
+                None
+            }
+        }
+    })
+}
+
+// Returns null if the passphrase is wrong or the config can't be read
+#[no_mangle]
+pub extern "C" fn clipboard_manager_new_encrypted(passphrase: *const c_char) -> *mut ClipboardManager {
+    ffi_new(|| {
+        let passphrase = unsafe { str_arg(passphrase).ok()? };
+        match ClipboardManager::new_encrypted(None, passphrase) {
+            Ok(manager) => Some(manager),
+            Err(e) => {
+                eprintln!("Failed to load config: {}", e);
+                None
+            }
+        }
+    })
+}
+
+// Any deferred changes are written before the manager is freed. Safe to call
+// while other threads are using the manager: calls already running finish
+// first, and later calls (and a second destroy) fail with Destroyed. Must
+// not be called from a change callback, which would wait on itself.
+#[no_mangle]
+pub extern "C" fn clipboard_manager_destroy(manager: *mut ClipboardManager) {
+    let calls = {
+        let mut live = live_managers().lock().unwrap_or_else(PoisonError::into_inner);
+        match live.remove(&(manager as usize)) {
+            Some(calls) => calls,
+            None => return
+        }
+    };
+    
+    let _ = panic::catch_unwind(|| {
+        // No callback may fire while the manager is being torn down
+        unsafe { (*manager).clear_callbacks() };
+        while calls.load(Ordering::SeqCst) > 0 {
+            thread::sleep(LOCK_RETRY_INTERVAL);
+        }
+        unsafe { drop(Box::from_raw(manager)) }
+    });
+}
+
+// Returns 1 if `manager` came from a constructor and has not been destroyed,
+// otherwise 0. Never dereferences the pointer.
+#[no_mangle]
+pub extern "C" fn clipboard_manager_is_alive(manager: *mut ClipboardManager) -> c_int {
+    let live = live_managers().lock().unwrap_or_else(PoisonError::into_inner);
+    if live.contains_key(&(manager as usize)) { 1 } else { 0 }
+}
+
+#[no_mangle]
+pub extern "C" fn clipboard_manager_add_register(
+    manager: *mut ClipboardManager,
+    name: *const c_char,
+    shortcut: *const c_char
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let name = unsafe { str_arg(name)? };
+        let shortcut = unsafe { str_arg(shortcut)? };
+        manager.add_register(name, shortcut)
+    })
+}
+
+// One element of the JSON array passed to clipboard_manager_add_registers
+#[derive(Deserialize)]
+struct RegisterEntry {
+    name: String,
+    #[serde(default)]
+    shortcut: String,
+    #[serde(default)]
+    content: String,
+}
+
+// `entries` is a JSON array of {"name", "shortcut", "content"} objects, where
+// shortcut and content may be omitted. Returns the number of registers
+// added, or a negative error code.
+#[no_mangle]
+pub extern "C" fn clipboard_manager_add_registers(
+    manager: *mut ClipboardManager,
+    entries: *const c_char
+) -> c_int {
+    ffi_int(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let entries = unsafe { str_arg(entries)? };
+        let entries: Vec<RegisterEntry> = serde_json::from_str(entries)
+            .map_err(|_| ClipboardError::InvalidFormat)?;
+        let entries = entries.into_iter()
+            .map(|e| (e.name, e.shortcut, e.content))
+            .collect();
+        manager.add_registers(entries).map(count_c_int)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn clipboard_manager_add_register_checked(
+    manager: *mut ClipboardManager,
+    name: *const c_char,
+    shortcut: *const c_char
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let name = unsafe { str_arg(name)? };
+        let shortcut = unsafe { str_arg(shortcut)? };
+        manager.add_register_checked(name, shortcut)
+    })
+}
+
+// Stores the normalized shortcut; see clipboard_manager_parse_shortcut to
+// obtain that form
+#[no_mangle]
+pub extern "C" fn clipboard_manager_add_register_validated(
+    manager: *mut ClipboardManager,
+    name: *const c_char,
+    shortcut: *const c_char
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let name = unsafe { str_arg(name)? };
+        let shortcut = unsafe { str_arg(shortcut)? };
+        manager.add_register_validated(name, shortcut).map(|_| ())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn clipboard_manager_update_shortcut_validated(
+    manager: *mut ClipboardManager,
+    name: *const c_char,
+    shortcut: *const c_char
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let name = unsafe { str_arg(name)? };
+        let shortcut = unsafe { str_arg(shortcut)? };
+        manager.update_shortcut_validated(name, shortcut).map(|_| ())
+    })
+}
+
+// Returns {"modifiers": <MODIFIER_* bits>, "key": "K", "normalized": "Cmd+K"},
+// or null if the shortcut does not parse
+#[no_mangle]
+pub extern "C" fn clipboard_manager_parse_shortcut(
+    shortcut: *const c_char
+) -> *mut c_char {
+    ffi_string(|| {
+        let shortcut = unsafe { str_arg(shortcut).ok()? };
+        let parsed = parse_shortcut(shortcut).ok()?;
+        let json = serde_json::json!({
+            "modifiers": parsed.modifiers,
+            "key": parsed.key,
+            "normalized": parsed.to_string(),
+        });
+        Some(json.to_string())
+    })
+}
+
+// Returns the name of the register owning `shortcut`, or null if it is free
+#[no_mangle]
+pub extern "C" fn clipboard_manager_shortcut_in_use(
+    manager: *mut ClipboardManager,
+    shortcut: *const c_char
+) -> *mut c_char {
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        let shortcut = unsafe { str_arg(shortcut).ok()? };
+        manager.shortcut_in_use(shortcut)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn clipboard_manager_update_register_content(
+    manager: *mut ClipboardManager,
+    name: *const c_char,
+    content: *const c_char
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let name = unsafe { str_arg(name)? };
+        let content = unsafe { str_arg(content)? };
+        manager.update_register_content(name, content)
+    })
+}
+
+// Stores the content as given even when normalize-on-store is enabled
+#[no_mangle]
+pub extern "C" fn clipboard_manager_update_register_content_raw(
+    manager: *mut ClipboardManager,
+    name: *const c_char,
+    content: *const c_char
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let name = unsafe { str_arg(name)? };
+        let content = unsafe { str_arg(content)? };
+        manager.update_register_content_raw(name, content)
+    })
+}
+
+// `normalize` is a boolean (non-zero = true). When set, content is stored
+// with LF line endings and no trailing whitespace on any line.
+#[no_mangle]
+pub extern "C" fn clipboard_manager_set_normalize_on_store(
+    manager: *mut ClipboardManager,
+    normalize: c_int
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        manager.set_normalize_on_store(normalize != 0)
+    })
+}
+
+// A null separator appends with no separator
+#[no_mangle]
+pub extern "C" fn clipboard_manager_append_register_content(
+    manager: *mut ClipboardManager,
+    name: *const c_char,
+    content: *const c_char,
+    separator: *const c_char
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let name = unsafe { str_arg(name)? };
+        let content = unsafe { str_arg(content)? };
+        let separator = if separator.is_null() {
+            None
+        } else {
+            Some(unsafe { str_arg(separator)? })
+        };
+        manager.append_register_content(name, content, separator)
+    })
+}
+
+// Returns the previous content, or null if the update failed
+#[no_mangle]
+pub extern "C" fn clipboard_manager_replace_register_content(
+    manager: *mut ClipboardManager,
+    name: *const c_char,
+    content: *const c_char
+) -> *mut c_char {
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        let name = unsafe { str_arg(name).ok()? };
+        let content = unsafe { str_arg(content).ok()? };
+        manager.replace_register_content(name, content).ok()
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn clipboard_manager_clear_register_content(
+    manager: *mut ClipboardManager,
+    name: *const c_char
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let name = unsafe { str_arg(name)? };
+        manager.clear_register_content(name)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn clipboard_manager_clear_all_contents(
+    manager: *mut ClipboardManager
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        manager.clear_all_contents()
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn clipboard_manager_get_register_content(
+    manager: *mut ClipboardManager,
+    name: *const c_char
+) -> *mut c_char {
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        let name = unsafe { str_arg(name).ok()? };
+        manager.get_register_content(name)
+    })
+}
+
+// Like clipboard_manager_get_register_content, but sets *out_found to 1 if
+// the register exists and 0 if not, so an empty register ("") can be told
+// apart from a missing one (null). If the register exists, null is only
+// returned for content containing a NUL byte. `out_found` may be null.
+#[no_mangle]
+pub extern "C" fn clipboard_manager_get_register_content_ex(
+    manager: *mut ClipboardManager,
+    name: *const c_char,
+    out_found: *mut c_int
+) -> *mut c_char {
+    if !out_found.is_null() {
+        unsafe { *out_found = 0; }
+    }
+    
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        let name = unsafe { str_arg(name).ok()? };
+        let content = manager.get_register_content(name)?;
+        if !out_found.is_null() {
+            unsafe { *out_found = 1; }
+        }
+        Some(content)
+    })
+}
+
+// Content with {{date}}, {{time}}, {{datetime}} and {{date:FORMAT}} tokens
+// filled in from the current local time; null if the register does not
+// exist. clipboard_manager_get_register_content always returns the tokens as
+// written.
+#[no_mangle]
+pub extern "C" fn clipboard_manager_get_register_content_rendered(
+    manager: *mut ClipboardManager,
+    name: *const c_char
+) -> *mut c_char {
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        let name = unsafe { str_arg(name).ok()? };
+        manager.get_register_content_rendered(name)
+    })
+}
+
+// Content with environment variable references expanded; null if the
+// register does not exist
+#[no_mangle]
+pub extern "C" fn clipboard_manager_get_register_content_expanded(
+    manager: *mut ClipboardManager,
+    name: *const c_char
+) -> *mut c_char {
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        let name = unsafe { str_arg(name).ok()? };
+        manager.get_register_content_expanded(name)
+    })
+}
+
+// `vars` is a JSON object of string values. Returns null if the register does
+// not exist or `vars` is not such an object.
+#[no_mangle]
+pub extern "C" fn clipboard_manager_get_register_content_with_vars(
+    manager: *mut ClipboardManager,
+    name: *const c_char,
+    vars: *const c_char
+) -> *mut c_char {
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        let name = unsafe { str_arg(name).ok()? };
+        let vars = unsafe { str_arg(vars).ok()? };
+        let vars: HashMap<String, String> = serde_json::from_str(vars).ok()?;
+        manager.get_register_content_with_vars(name, &vars)
+    })
+}
+
+// Returns the register as a JSON object, or null if it does not exist
+#[no_mangle]
+pub extern "C" fn clipboard_manager_get_register(
+    manager: *mut ClipboardManager,
+    name: *const c_char
+) -> *mut c_char {
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        let name = unsafe { str_arg(name).ok()? };
+        manager.get_register(name)
+    })
+}
+
+// Like clipboard_manager_get_register, but first adds the register (empty,
+// with `default_shortcut`) if it does not exist. Null on error.
+#[no_mangle]
+pub extern "C" fn clipboard_manager_get_or_create(
+    manager: *mut ClipboardManager,
+    name: *const c_char,
+    default_shortcut: *const c_char
+) -> *mut c_char {
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        let name = unsafe { str_arg(name).ok()? };
+        let default_shortcut = unsafe { str_arg(default_shortcut).ok()? };
+        manager.get_or_create(name, default_shortcut).ok()
+    })
+}
+
+// `data` points to `len` bytes; it may be null only when `len` is 0. A
+// `mime` of text/plain is rejected with InvalidArgument.
+#[no_mangle]
+pub extern "C" fn clipboard_manager_update_register_binary(
+    manager: *mut ClipboardManager,
+    name: *const c_char,
+    mime: *const c_char,
+    data: *const u8,
+    len: usize
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let name = unsafe { str_arg(name)? };
+        let mime = unsafe { str_arg(mime)? };
+        let data = if len == 0 {
+            &[][..]
+        } else if data.is_null() {
+            return Err(ClipboardError::NullPointer);
+        } else {
+            unsafe { std::slice::from_raw_parts(data, len) }
+        };
+        manager.update_register_binary(name, mime, data)
+    })
+}
+
+// Returns a JSON array of previous contents (oldest first), or null if the
+// register does not exist
+#[no_mangle]
+pub extern "C" fn clipboard_manager_get_register_history(
+    manager: *mut ClipboardManager,
+    name: *const c_char
+) -> *mut c_char {
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        let name = unsafe { str_arg(name).ok()? };
+        manager.get_register_history(name)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn clipboard_manager_undo_register_content(
+    manager: *mut ClipboardManager,
+    name: *const c_char
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let name = unsafe { str_arg(name)? };
+        manager.undo_register_content(name)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn clipboard_manager_remove_register(
+    manager: *mut ClipboardManager,
+    name: *const c_char
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let name = unsafe { str_arg(name)? };
+        manager.remove_register(name)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn clipboard_manager_rename_register(
+    manager: *mut ClipboardManager,
+    old_name: *const c_char,
+    new_name: *const c_char
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let old_name = unsafe { str_arg(old_name)? };
+        let new_name = unsafe { str_arg(new_name)? };
+        manager.rename_register(old_name, new_name)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn clipboard_manager_duplicate_register(
+    manager: *mut ClipboardManager,
+    src: *const c_char,
+    new_name: *const c_char
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let src = unsafe { str_arg(src)? };
+        let new_name = unsafe { str_arg(new_name)? };
+        manager.duplicate_register(src, new_name)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn clipboard_manager_merge_registers(
+    manager: *mut ClipboardManager,
+    target: *const c_char,
+    source: *const c_char,
+    separator: *const c_char
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let target = unsafe { str_arg(target)? };
+        let source = unsafe { str_arg(source)? };
+        let separator = unsafe { str_arg(separator)? };
+        manager.merge_registers(target, source, separator)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn clipboard_manager_swap_contents(
+    manager: *mut ClipboardManager,
+    a: *const c_char,
+    b: *const c_char
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let a = unsafe { str_arg(a)? };
+        let b = unsafe { str_arg(b)? };
+        manager.swap_contents(a, b)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn clipboard_manager_update_shortcut(
+    manager: *mut ClipboardManager,
+    name: *const c_char,
+    shortcut: *const c_char
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let name = unsafe { str_arg(name)? };
+        let shortcut = unsafe { str_arg(shortcut)? };
+        manager.update_shortcut(name, shortcut)
+    })
+}
+
+// Sets content and shortcut together with one save; a null `content` or
+// `shortcut` leaves it unchanged
+#[no_mangle]
+pub extern "C" fn clipboard_manager_update_register(
+    manager: *mut ClipboardManager,
+    name: *const c_char,
+    content: *const c_char,
+    shortcut: *const c_char
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let name = unsafe { str_arg(name)? };
+        let content = if content.is_null() {
+            None
+        } else {
+            Some(unsafe { str_arg(content)? })
+        };
+        let shortcut = if shortcut.is_null() {
+            None
+        } else {
+            Some(unsafe { str_arg(shortcut)? })
+        };
+        manager.update_register(name, content, shortcut)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn clipboard_manager_update_shortcut_checked(
+    manager: *mut ClipboardManager,
+    name: *const c_char,
+    shortcut: *const c_char
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let name = unsafe { str_arg(name)? };
+        let shortcut = unsafe { str_arg(shortcut)? };
+        manager.update_shortcut_checked(name, shortcut)
+    })
+}
+
+// A max of 0 removes the cap. See ClipboardState::make_room for which
+// registers are evicted when it is reached.
+#[no_mangle]
+pub extern "C" fn clipboard_manager_set_max_registers(
+    manager: *mut ClipboardManager,
+    max: usize
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        manager.set_max_registers(if max == 0 { None } else { Some(max) })
+    })
+}
+
+// A max of 0 removes the limit
+#[no_mangle]
+pub extern "C" fn clipboard_manager_set_max_content_bytes(
+    manager: *mut ClipboardManager,
+    max: usize
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        manager.set_max_content_bytes(if max == 0 { None } else { Some(max) })
+    })
+}
+
+// Returns matches in the same JSON shape as clipboard_manager_get_all_registers
+#[no_mangle]
+pub extern "C" fn clipboard_manager_search(
+    manager: *mut ClipboardManager,
+    query: *const c_char
+) -> *mut c_char {
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        let query = unsafe { str_arg(query).ok()? };
+        manager.search(query, false)
+    })
+}
+
+// `mode` is a SearchMode: 0 = exact, 1 = prefix, 2 = substring. Returns null
+// for an unknown mode.
+#[no_mangle]
+pub extern "C" fn clipboard_manager_search_with_mode(
+    manager: *mut ClipboardManager,
+    query: *const c_char,
+    mode: c_int
+) -> *mut c_char {
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        let query = unsafe { str_arg(query).ok()? };
+        let mode = SearchMode::from_c_int(mode)?;
+        manager.search_with_mode(query, mode, false)
+    })
+}
+
+// Returns [[name, register, distance], ...] sorted best match first; see
+// ClipboardState::search_fuzzy
+#[no_mangle]
+pub extern "C" fn clipboard_manager_search_fuzzy(
+    manager: *mut ClipboardManager,
+    query: *const c_char,
+    max_distance: usize
+) -> *mut c_char {
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        let query = unsafe { str_arg(query).ok()? };
+        manager.search_fuzzy(query, max_distance, false)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn clipboard_manager_add_tag(
+    manager: *mut ClipboardManager,
+    name: *const c_char,
+    tag: *const c_char
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let name = unsafe { str_arg(name)? };
+        let tag = unsafe { str_arg(tag)? };
+        manager.add_tag(name, tag)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn clipboard_manager_remove_tag(
+    manager: *mut ClipboardManager,
+    name: *const c_char,
+    tag: *const c_char
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let name = unsafe { str_arg(name)? };
+        let tag = unsafe { str_arg(tag)? };
+        manager.remove_tag(name, tag)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn clipboard_manager_get_registers_by_tag(
+    manager: *mut ClipboardManager,
+    tag: *const c_char
+) -> *mut c_char {
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        let tag = unsafe { str_arg(tag).ok()? };
+        manager.get_registers_by_tag(tag, false)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn clipboard_manager_set_history_limit(
+    manager: *mut ClipboardManager,
+    limit: usize
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        manager.set_history_limit(limit)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn clipboard_manager_move_register(
+    manager: *mut ClipboardManager,
+    name: *const c_char,
+    new_index: usize
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let name = unsafe { str_arg(name)? };
+        manager.move_register(name, new_index)
+    })
+}
+
+// Moves the register to the top of the list, below any pinned registers
+#[no_mangle]
+pub extern "C" fn clipboard_manager_promote(
+    manager: *mut ClipboardManager,
+    name: *const c_char
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let name = unsafe { str_arg(name)? };
+        manager.promote(name)
+    })
+}
+
+// Returns the number of registers, or a negative error code
+#[no_mangle]
+pub extern "C" fn clipboard_manager_register_count(
+    manager: *mut ClipboardManager
+) -> c_int {
+    ffi_int(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        manager.register_count().map(count_c_int)
+    })
+}
+
+// Returns 1 if there was no config file when the manager was created, 0 if
+// there was (even an empty one), or a negative error code
+#[no_mangle]
+pub extern "C" fn clipboard_manager_is_first_run(
+    manager: *mut ClipboardManager
+) -> c_int {
+    ffi_int(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        Ok(if manager.is_first_run() { 1 } else { 0 })
+    })
+}
+
+// Returns 1 if the register exists, 0 if not, or a negative error code
+#[no_mangle]
+pub extern "C" fn clipboard_manager_contains_register(
+    manager: *mut ClipboardManager,
+    name: *const c_char
+) -> c_int {
+    ffi_int(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let name = unsafe { str_arg(name)? };
+        manager.contains_register(name).map(|found| if found { 1 } else { 0 })
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn clipboard_manager_mark_used(
+    manager: *mut ClipboardManager,
+    name: *const c_char
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let name = unsafe { str_arg(name)? };
+        manager.mark_used(name)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn clipboard_manager_get_recently_used(
+    manager: *mut ClipboardManager,
+    limit: usize
+) -> *mut c_char {
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        manager.get_recently_used(limit, false)
+    })
+}
+
+// Bytes of content across the active profile's registers, or a negative
+// error code
+#[no_mangle]
+pub extern "C" fn clipboard_manager_total_content_bytes(
+    manager: *mut ClipboardManager
+) -> i64 {
+    ffi_i64(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let bytes = manager.total_content_bytes()?;
+        Ok(bytes.min(i64::MAX as usize) as i64)
+    })
+}
+
+// Approximate size of the config file in bytes, or a negative error code
+#[no_mangle]
+pub extern "C" fn clipboard_manager_estimated_file_size(
+    manager: *mut ClipboardManager
+) -> i64 {
+    ffi_i64(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let bytes = manager.estimated_file_size()?;
+        Ok(bytes.min(i64::MAX as usize) as i64)
+    })
+}
+
+// Returns the use count, or a negative error code
+#[no_mangle]
+pub extern "C" fn clipboard_manager_get_use_count(
+    manager: *mut ClipboardManager,
+    name: *const c_char
+) -> i64 {
+    ffi_i64(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let name = unsafe { str_arg(name)? };
+        let count = manager.get_use_count(name).ok_or(ClipboardError::NotFound)?;
+        Ok(count.min(i64::MAX as u64) as i64)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn clipboard_manager_get_most_used(
+    manager: *mut ClipboardManager,
+    limit: usize
+) -> *mut c_char {
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        manager.get_most_used(limit, false)
+    })
+}
+
+// `hidden` is a boolean (non-zero = true). Hidden registers are only listed
+// by clipboard_manager_get_all_registers_including_hidden.
+#[no_mangle]
+pub extern "C" fn clipboard_manager_set_hidden(
+    manager: *mut ClipboardManager,
+    name: *const c_char,
+    hidden: c_int
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let name = unsafe { str_arg(name)? };
+        manager.set_hidden(name, hidden != 0)
+    })
+}
+
+// `locked` is a boolean (non-zero = true)
+#[no_mangle]
+pub extern "C" fn clipboard_manager_set_locked(
+    manager: *mut ClipboardManager,
+    name: *const c_char,
+    locked: c_int
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let name = unsafe { str_arg(name)? };
+        manager.set_locked(name, locked != 0)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn clipboard_manager_set_pinned(
+    manager: *mut ClipboardManager,
+    name: *const c_char,
+    pinned: c_int
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let name = unsafe { str_arg(name)? };
+        manager.set_pinned(name, pinned != 0)
+    })
+}
+
+// Returns a JSON array of register names, sorted
+#[no_mangle]
+pub extern "C" fn clipboard_manager_register_names(
+    manager: *mut ClipboardManager
+) -> *mut c_char {
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        manager.register_names(false)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn clipboard_manager_get_all_registers(
+    manager: *mut ClipboardManager
+) -> *mut c_char {
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        manager.get_all_registers()
+    })
+}
+
+// Like clipboard_manager_get_all_registers, for list views: each content is
+// cut to `max_chars` characters (with "…" appended), and each register has
+// "truncated": true if its content was cut. "history" is always empty.
+// Fetch the full content with clipboard_manager_get_register_content.
+#[no_mangle]
+pub extern "C" fn clipboard_manager_get_all_registers_preview(
+    manager: *mut ClipboardManager,
+    max_chars: usize
+) -> *mut c_char {
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        manager.get_all_registers_preview(max_chars)
+    })
+}
+
+// Same JSON shape as clipboard_manager_get_all_registers, hidden registers
+// included
+#[no_mangle]
+pub extern "C" fn clipboard_manager_get_all_registers_including_hidden(
+    manager: *mut ClipboardManager
+) -> *mut c_char {
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        manager.get_all_registers_including_hidden()
+    })
+}
+
+// Returns [{"name": ..., "content": ..., "shortcut": ..., ...}] in display
+// order
+#[no_mangle]
+pub extern "C" fn clipboard_manager_get_all_registers_v2(
+    manager: *mut ClipboardManager
+) -> *mut c_char {
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        manager.get_all_registers_v2()
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn clipboard_manager_export_to(
+    manager: *mut ClipboardManager,
+    path: *const c_char
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let path = unsafe { str_arg(path)? };
+        manager.export_to(Path::new(path))
+    })
+}
+
+// `replace` and `overwrite` are booleans (non-zero = true), see
+// ClipboardState::import_from
+#[no_mangle]
+pub extern "C" fn clipboard_manager_import_from(
+    manager: *mut ClipboardManager,
+    path: *const c_char,
+    replace: c_int,
+    overwrite: c_int
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let path = unsafe { str_arg(path)? };
+        manager.import_from(Path::new(path), replace != 0, overwrite != 0).map_err(|e| e.error)
+    })
+}
+
+// `on_conflict` is an OnConflict: 0 = skip, 1 = overwrite, 2 = add under
+// "name (N)". InvalidArgument for any other value.
+#[no_mangle]
+pub extern "C" fn clipboard_manager_import_from_with(
+    manager: *mut ClipboardManager,
+    path: *const c_char,
+    replace: c_int,
+    on_conflict: c_int
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let path = unsafe { str_arg(path)? };
+        let on_conflict = OnConflict::from_c_int(on_conflict).ok_or(ClipboardError::InvalidArgument)?;
+        manager.import_from_with(Path::new(path), replace != 0, on_conflict).map_err(|e| e.error)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn clipboard_manager_export_csv(
+    manager: *mut ClipboardManager
+) -> *mut c_char {
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        manager.export_csv()
+    })
+}
+
+// Returns the number of registers imported, or a negative error code. For
+// a malformed row the line it starts on goes to *out_line, as for
+// clipboard_manager_import_jsonl.
+#[no_mangle]
+pub extern "C" fn clipboard_manager_import_csv(
+    manager: *mut ClipboardManager,
+    data: *const c_char,
+    replace: c_int,
+    out_line: *mut c_int
+) -> c_int {
+    unsafe { set_out_line(out_line, None) };
+    
+    ffi_int(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let data = unsafe { str_arg(data)? };
+        let result = manager.import_csv(data, replace != 0);
+        unsafe { import_count(result, out_line) }
+    })
+}
+
+// Returns the registers as an XML property list; see
+// ClipboardState::export_plist
+#[no_mangle]
+pub extern "C" fn clipboard_manager_export_plist(
+    manager: *mut ClipboardManager
+) -> *mut c_char {
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        manager.export_plist().ok()
+    })
+}
+
+// `replace` is a boolean (non-zero = true). Returns the number of registers
+// imported, or a negative error code.
+#[no_mangle]
+pub extern "C" fn clipboard_manager_import_plist(
+    manager: *mut ClipboardManager,
+    data: *const c_char,
+    replace: c_int
+) -> c_int {
+    ffi_int(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let data = unsafe { str_arg(data)? };
+        manager.import_plist(data, replace != 0)
+            .map(count_c_int)
+            .map_err(|e| e.error)
+    })
+}
+
+// Writes the registers to `path` as JSON Lines; see
+// ClipboardState::export_jsonl
+#[no_mangle]
+pub extern "C" fn clipboard_manager_export_jsonl(
+    manager: *mut ClipboardManager,
+    path: *const c_char
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let path = unsafe { str_arg(path)? };
+        manager.export_jsonl(Path::new(path))
+    })
+}
+
+// `replace` is a boolean (non-zero = true). Returns the number of registers
+// imported, or a negative error code; a malformed line is reported as
+// InvalidFormat, or InvalidName for a blank name, and its number (from 1)
+// goes to *out_line. *out_line is 0 for success and any other error.
+// `out_line` may be null.
+#[no_mangle]
+pub extern "C" fn clipboard_manager_import_jsonl(
+    manager: *mut ClipboardManager,
+    path: *const c_char,
+    replace: c_int,
+    out_line: *mut c_int
+) -> c_int {
+    unsafe { set_out_line(out_line, None) };
+    
+    ffi_int(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let path = unsafe { str_arg(path)? };
+        let result = manager.import_jsonl(Path::new(path), replace != 0);
+        unsafe { import_count(result, out_line) }
+    })
+}
+
+// Returns the number of registers imported, or a negative error code.
+// `on_conflict` as for clipboard_manager_import_from_with and `out_line` as
+// for clipboard_manager_import_csv.
+#[no_mangle]
+pub extern "C" fn clipboard_manager_import_csv_with(
+    manager: *mut ClipboardManager,
+    data: *const c_char,
+    replace: c_int,
+    on_conflict: c_int,
+    out_line: *mut c_int
+) -> c_int {
+    unsafe { set_out_line(out_line, None) };
     
-    if manager.remove_register(name) { 1 } else { 0 }
+    ffi_int(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let data = unsafe { str_arg(data)? };
+        let on_conflict = OnConflict::from_c_int(on_conflict).ok_or(ClipboardError::InvalidArgument)?;
+        let result = manager.import_csv_with(data, replace != 0, on_conflict);
+        unsafe { import_count(result, out_line) }
+    })
+}
+
+// Passing a null callback removes the current one
+#[no_mangle]
+pub extern "C" fn clipboard_manager_set_callback(
+    manager: *mut ClipboardManager,
+    callback: Option<ChangeCallbackFn>,
+    user_data: *mut c_void
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        manager.set_callback(callback, user_data);
+        Ok(())
+    })
+}
+
+// Like clipboard_manager_set_callback but only for changes to `name`. Passing
+// a null callback removes the one registered for `name`.
+#[no_mangle]
+pub extern "C" fn clipboard_manager_set_callback_for(
+    manager: *mut ClipboardManager,
+    name: *const c_char,
+    callback: Option<ChangeCallbackFn>,
+    user_data: *mut c_void
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let name = unsafe { str_arg(name)? };
+        manager.set_callback_for(name, callback, user_data);
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn clipboard_manager_flush(
+    manager: *mut ClipboardManager
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        manager.flush()
+    })
+}
+
+// An interval of 0 disables batching and saves on every mutation
+#[no_mangle]
+pub extern "C" fn clipboard_manager_set_save_interval(
+    manager: *mut ClipboardManager,
+    interval_ms: u64
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let interval = if interval_ms == 0 { None } else { Some(Duration::from_millis(interval_ms)) };
+        manager.set_save_interval(interval)
+    })
+}
+
+// Call on startup: returns CLIPBOARD_OK if the manager is usable and its
+// config can be saved, otherwise the code describing the first problem
+#[no_mangle]
+pub extern "C" fn clipboard_manager_health_check(
+    manager: *mut ClipboardManager
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        manager.health_check()
+    })
+}
+
+// Returns 0 for a null manager
+#[no_mangle]
+pub extern "C" fn clipboard_manager_revision(
+    manager: *mut ClipboardManager
+) -> u64 {
+    match unsafe { manager_ref(manager) } {
+        Ok(manager) => manager.revision(),
+        Err(_) => 0
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn clipboard_manager_active_profile(
+    manager: *mut ClipboardManager
+) -> *mut c_char {
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        manager.active_profile()
+    })
+}
+
+// Returns a JSON array of profile names, sorted
+#[no_mangle]
+pub extern "C" fn clipboard_manager_list_profiles(
+    manager: *mut ClipboardManager
+) -> *mut c_char {
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        manager.list_profiles()
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn clipboard_manager_create_profile(
+    manager: *mut ClipboardManager,
+    name: *const c_char
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let name = unsafe { str_arg(name)? };
+        manager.create_profile(name)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn clipboard_manager_switch_profile(
+    manager: *mut ClipboardManager,
+    name: *const c_char
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let name = unsafe { str_arg(name)? };
+        manager.switch_profile(name)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn clipboard_manager_delete_profile(
+    manager: *mut ClipboardManager,
+    name: *const c_char
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let name = unsafe { str_arg(name)? };
+        manager.delete_profile(name)
+    })
+}
+
+// Returns a JSON array of problems found in the active profile; never writes
+#[no_mangle]
+pub extern "C" fn clipboard_manager_validate(
+    manager: *mut ClipboardManager
+) -> *mut c_char {
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        manager.validate()
+    })
+}
+
+// Returns {"total": N, "registers": [...]} for `limit` registers starting at
+// `offset` in display order
+#[no_mangle]
+pub extern "C" fn clipboard_manager_get_registers_page(
+    manager: *mut ClipboardManager,
+    offset: usize,
+    limit: usize
+) -> *mut c_char {
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        manager.get_registers_page(offset, limit)
+    })
+}
+
+// Like clipboard_manager_get_all_registers, ordered by `sort`: 0 =
+// alphabetical, 1 = natural ("file2" before "file10"), 2 = most recently
+// used, 3 = most used. Pinned registers still come first. Returns null for an
+// unknown sort.
+#[no_mangle]
+pub extern "C" fn clipboard_manager_get_all_registers_sorted(
+    manager: *mut ClipboardManager,
+    sort: c_int
+) -> *mut c_char {
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        let sort = SortOrder::from_c_int(sort)?;
+        manager.get_all_registers_sorted(sort)
+    })
+}
+
+// clipboard_manager_get_registers_page with the order of
+// clipboard_manager_get_all_registers_sorted
+#[no_mangle]
+pub extern "C" fn clipboard_manager_get_registers_page_sorted(
+    manager: *mut ClipboardManager,
+    offset: usize,
+    limit: usize,
+    sort: c_int
+) -> *mut c_char {
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        let sort = SortOrder::from_c_int(sort)?;
+        manager.get_registers_page_sorted(offset, limit, sort)
+    })
+}
+
+// Returns the absolute path of the config file. Non-UTF-8 components are
+// replaced with U+FFFD.
+#[no_mangle]
+pub extern "C" fn clipboard_manager_config_path(
+    manager: *mut ClipboardManager
+) -> *mut c_char {
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        Some(manager.config_path()?.to_string_lossy().into_owned())
+    })
+}
+
+// Returns null if the register does not exist
+#[no_mangle]
+pub extern "C" fn clipboard_manager_get_shortcut(
+    manager: *mut ClipboardManager,
+    name: *const c_char
+) -> *mut c_char {
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        let name = unsafe { str_arg(name).ok()? };
+        manager.get_shortcut(name)
+    })
+}
+
+// Returns {"name": "shortcut", ...} for every register that has a shortcut,
+// without any content
+#[no_mangle]
+pub extern "C" fn clipboard_manager_get_shortcut_map(
+    manager: *mut ClipboardManager
+) -> *mut c_char {
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        manager.shortcut_map()
+    })
+}
+
+// Returns [name, register] as JSON for the register bound to `shortcut`, or
+// null if none is
+#[no_mangle]
+pub extern "C" fn clipboard_manager_find_by_shortcut(
+    manager: *mut ClipboardManager,
+    shortcut: *const c_char
+) -> *mut c_char {
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        let shortcut = unsafe { str_arg(shortcut).ok()? };
+        manager.find_by_shortcut(shortcut)
+    })
+}
+
+// `compress` is a boolean (non-zero = true). The config is re-saved gzipped
+// (or plain) right away, or at the next flush when saves are deferred.
+#[no_mangle]
+pub extern "C" fn clipboard_manager_set_compression(
+    manager: *mut ClipboardManager,
+    compress: c_int
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        manager.set_compression(compress != 0)
+    })
+}
+
+// `compact` is a boolean (non-zero = true); see ClipboardState::compact
+#[no_mangle]
+pub extern "C" fn clipboard_manager_set_compact(
+    manager: *mut ClipboardManager,
+    compact: c_int
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        manager.set_compact(compact != 0)
+    })
+}
+
+// Trims histories and rewrites the config immediately
+#[no_mangle]
+pub extern "C" fn clipboard_manager_compact(
+    manager: *mut ClipboardManager
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        manager.compact()
+    })
+}
+
+// Returns removed registers, oldest first, in the same JSON shape as
+// clipboard_manager_get_all_registers
+#[no_mangle]
+pub extern "C" fn clipboard_manager_get_trash(
+    manager: *mut ClipboardManager
+) -> *mut c_char {
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        manager.get_trash()
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn clipboard_manager_restore_from_trash(
+    manager: *mut ClipboardManager,
+    name: *const c_char
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let name = unsafe { str_arg(name)? };
+        manager.restore_from_trash(name)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn clipboard_manager_empty_trash(
+    manager: *mut ClipboardManager
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        manager.empty_trash()
+    })
+}
+
+// Returns a JSON array of {"op", "line"} objects describing how to get from
+// the register's content to `other`, or null if the register does not exist
+#[no_mangle]
+pub extern "C" fn clipboard_manager_diff_content(
+    manager: *mut ClipboardManager,
+    name: *const c_char,
+    other: *const c_char
+) -> *mut c_char {
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        let name = unsafe { str_arg(name).ok()? };
+        let other = unsafe { str_arg(other).ok()? };
+        manager.diff_content(name, other)
+    })
+}
+
+// Returns CLIPBOARD_OK, or Io / InvalidFormat if the file can't be read or
+// parsed, in which case the in-memory state is kept
+#[no_mangle]
+pub extern "C" fn clipboard_manager_reload(
+    manager: *mut ClipboardManager
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        manager.reload()
+    })
+}
+
+// Reloads automatically when the config file is changed by another process
+#[no_mangle]
+pub extern "C" fn clipboard_manager_enable_auto_reload(
+    manager: *mut ClipboardManager
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        manager.enable_auto_reload()
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn clipboard_manager_disable_auto_reload(
+    manager: *mut ClipboardManager
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        manager.disable_auto_reload();
+        Ok(())
+    })
+}
+
+// A null or empty format clears it
+#[no_mangle]
+pub extern "C" fn clipboard_manager_set_format(
+    manager: *mut ClipboardManager,
+    name: *const c_char,
+    format: *const c_char
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let name = unsafe { str_arg(name)? };
+        let format = if format.is_null() {
+            ""
+        } else {
+            unsafe { str_arg(format)? }
+        };
+        manager.set_format(name, format)
+    })
+}
+
+// Returns {"valid": bool, "error": "..."} (error only when invalid), or null
+// if the register does not exist
+#[no_mangle]
+pub extern "C" fn clipboard_manager_validate_register(
+    manager: *mut ClipboardManager,
+    name: *const c_char
+) -> *mut c_char {
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        let name = unsafe { str_arg(name).ok()? };
+        manager.validate_register(name)
+    })
+}
+
+// Null or empty `tag` / `format` match any register. Returns matches in the
+// same JSON shape as clipboard_manager_get_all_registers.
+#[no_mangle]
+pub extern "C" fn clipboard_manager_filter(
+    manager: *mut ClipboardManager,
+    tag: *const c_char,
+    format: *const c_char
+) -> *mut c_char {
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        let tag = if tag.is_null() {
+            ""
+        } else {
+            unsafe { str_arg(tag).ok()? }
+        };
+        let format = if format.is_null() {
+            ""
+        } else {
+            unsafe { str_arg(format).ok()? }
+        };
+        let tag = Some(tag).filter(|t| !t.is_empty());
+        let format = Some(format).filter(|f| !f.is_empty());
+        manager.filter(tag, format, false)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn clipboard_manager_move_register_to_profile(
+    manager: *mut ClipboardManager,
+    name: *const c_char,
+    target_profile: *const c_char
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let name = unsafe { str_arg(name)? };
+        let target_profile = unsafe { str_arg(target_profile)? };
+        manager.move_register_to_profile(name, target_profile)
+    })
+}
+
+// Returns the register's raw bytes (decoded for binary registers, UTF-8 for
+// text) and writes their length to `out_len`. Null, with *out_len = 0, if
+// the register does not exist. Free with clipboard_manager_free_bytes.
+#[no_mangle]
+pub extern "C" fn clipboard_manager_get_register_content_bytes(
+    manager: *mut ClipboardManager,
+    name: *const c_char,
+    out_len: *mut usize
+) -> *mut u8 {
+    ffi_bytes(out_len, || {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        let name = unsafe { str_arg(name).ok()? };
+        manager.get_register_binary(name)
+    })
+}
+
+// Keeps up to `count` previous configs next to the live one; 0 disables
+// backups
+#[no_mangle]
+pub extern "C" fn clipboard_manager_set_backup_count(
+    manager: *mut ClipboardManager,
+    count: usize
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        manager.set_backup_count(count)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn clipboard_manager_list_backups(
+    manager: *mut ClipboardManager
+) -> *mut c_char {
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        manager.list_backups()
+    })
+}
+
+// Clears everything but the save settings and saves an empty config; see
+// ClipboardState::reset. `backup` is a boolean (non-zero = true); when set
+// the old config becomes backup 1 first, even with a backup count of 0, so
+// clipboard_manager_restore_backup(manager, 1) undoes the reset. Io if the
+// backup or the save fails, in which case nothing was reset.
+#[no_mangle]
+pub extern "C" fn clipboard_manager_reset(
+    manager: *mut ClipboardManager,
+    backup: c_int
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        manager.reset(backup != 0)
+    })
+}
+
+// `index` as reported by clipboard_manager_list_backups, 1 being the newest
+#[no_mangle]
+pub extern "C" fn clipboard_manager_restore_backup(
+    manager: *mut ClipboardManager,
+    index: usize
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        manager.restore_backup(index)
+    })
 }
 
+// Creates or overwrites a register in one step. Returns a SetOutcome (0 if
+// the register was created, 1 if it was updated) or a negative
+// ClipboardError.
 #[no_mangle]
-pub extern "C" fn clipboard_manager_update_shortcut(
+pub extern "C" fn clipboard_manager_set_register(
     manager: *mut ClipboardManager,
     name: *const c_char,
+    content: *const c_char,
     shortcut: *const c_char
 ) -> c_int {
-    let manager = unsafe {
-        assert!(!manager.is_null());
-        &*manager
-    };
-    
-    let name = unsafe {
-        assert!(!name.is_null());
-        CStr::from_ptr(name).to_str().unwrap_or("")
-    };
-    
-    let shortcut = unsafe {
-        assert!(!shortcut.is_null());
-        CStr::from_ptr(shortcut).to_str().unwrap_or("")
-    };
-    
-    if manager.update_shortcut(name, shortcut) { 1 } else { 0 }
+    ffi_int(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let name = unsafe { str_arg(name)? };
+        let content = unsafe { str_arg(content)? };
+        let shortcut = unsafe { str_arg(shortcut)? };
+        manager.set_register(name, content, shortcut).map(|outcome| outcome as c_int)
+    })
 }
 
+// Returns the register's content hash (see ClipboardRegister::content_hash),
+// also included as "content_hash" in clipboard_manager_get_all_registers, or
+// null if it does not exist
 #[no_mangle]
-pub extern "C" fn clipboard_manager_get_all_registers(
+pub extern "C" fn clipboard_manager_get_register_hash(
+    manager: *mut ClipboardManager,
+    name: *const c_char
+) -> *mut c_char {
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        let name = unsafe { str_arg(name).ok()? };
+        manager.get_register_hash(name)
+    })
+}
+
+// Calls that can't get at the state within `timeout_ms` (500 by default)
+// fail with Busy, or return null, instead of blocking. 0 waits indefinitely.
+#[no_mangle]
+pub extern "C" fn clipboard_manager_set_lock_timeout(
+    manager: *mut ClipboardManager,
+    timeout_ms: u64
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let timeout = if timeout_ms == 0 { None } else { Some(Duration::from_millis(timeout_ms)) };
+        manager.set_lock_timeout(timeout);
+        Ok(())
+    })
+}
+
+// The register is removed by the next clipboard_manager_purge_expired once
+// `seconds` have passed; 0 removes its expiry
+#[no_mangle]
+pub extern "C" fn clipboard_manager_set_register_ttl(
+    manager: *mut ClipboardManager,
+    name: *const c_char,
+    seconds: u64
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let name = unsafe { str_arg(name)? };
+        manager.set_register_ttl(name, if seconds == 0 { None } else { Some(seconds) })
+    })
+}
+
+// Returns the number of registers removed, or a negative error code
+#[no_mangle]
+pub extern "C" fn clipboard_manager_purge_expired(
+    manager: *mut ClipboardManager
+) -> c_int {
+    ffi_int(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        manager.purge_expired().map(count_c_int)
+    })
+}
+
+// `pretty` is a boolean (non-zero = indented JSON from every call that
+// returns JSON)
+#[no_mangle]
+pub extern "C" fn clipboard_manager_set_output_pretty(
+    manager: *mut ClipboardManager,
+    pretty: c_int
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        manager.set_output_pretty(pretty != 0);
+        Ok(())
+    })
+}
+
+// Returns the whole state as JSON, to hand back to
+// clipboard_manager_restore_snapshot to undo a multi-step edit
+#[no_mangle]
+pub extern "C" fn clipboard_manager_snapshot(
     manager: *mut ClipboardManager
 ) -> *mut c_char {
-    let manager = unsafe {
-        assert!(!manager.is_null());
-        &*manager
-    };
-    
-    let json = manager.get_all_registers();
-    
-    match CString::new(json) {
-        Ok(c_str) => c_str.into_raw(),
-        Err(_) => std::ptr::null_mut()
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        manager.snapshot_json()
+    })
+}
+
+// Replaces the whole state with a snapshot and saves it. InvalidFormat if
+// `json` is not a snapshot, in which case nothing changes.
+#[no_mangle]
+pub extern "C" fn clipboard_manager_restore_snapshot(
+    manager: *mut ClipboardManager,
+    json: *const c_char
+) -> c_int {
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let json = unsafe { str_arg(json)? };
+        manager.restore_snapshot_json(json)
+    })
+}
+
+// Applies a JSON array of ops under one lock and saves once, e.g.
+// [{"op": "rename", "name": "a", "new_name": "b"},
+//  {"op": "set_content", "name": "b", "content": "..."},
+//  {"op": "set_shortcut", "name": "b", "shortcut": "cmd+1"}]
+// Ops are "add" (name, optional shortcut), "remove" (name), "rename" (name,
+// new_name), "set_content" (name, content) and "set_shortcut" (name,
+// shortcut). If any op fails nothing is changed. Returns the report described
+// at ClipboardManager::apply_batch_json; null only if `manager` or `json` is
+// invalid.
+#[no_mangle]
+pub extern "C" fn clipboard_manager_apply_batch(
+    manager: *mut ClipboardManager,
+    json: *const c_char
+) -> *mut c_char {
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        let json = unsafe { str_arg(json).ok()? };
+        manager.apply_batch_json(json)
+    })
+}
+
+// Adds a register under `base_name`, or "base_name (N)" if that is taken,
+// and returns the name used; null on error
+#[no_mangle]
+pub extern "C" fn clipboard_manager_add_register_unique(
+    manager: *mut ClipboardManager,
+    base_name: *const c_char,
+    shortcut: *const c_char
+) -> *mut c_char {
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        let base_name = unsafe { str_arg(base_name).ok()? };
+        let shortcut = unsafe { str_arg(shortcut).ok()? };
+        manager.add_register_unique(base_name, shortcut).ok()
+    })
+}
+
+// Returns the register's RegisterStats as JSON, or null if it does not
+// exist
+#[no_mangle]
+pub extern "C" fn clipboard_manager_get_register_stats(
+    manager: *mut ClipboardManager,
+    name: *const c_char
+) -> *mut c_char {
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        let name = unsafe { str_arg(name).ok()? };
+        manager.register_stats(name)
+    })
+}
+
+// Returns e.g. "0.1.0 config 1": the library version, then the config
+// format version (see clipboard_manager_config_version). The string is
+// static; do NOT pass it to clipboard_manager_free_string.
+#[no_mangle]
+pub extern "C" fn clipboard_manager_version() -> *const c_char {
+    static VERSION: OnceLock<CString> = OnceLock::new();
+    VERSION.get_or_init(|| {
+        let version = format!("{} config {}", env!("CARGO_PKG_VERSION"), CONFIG_VERSION);
+        CString::new(version).unwrap_or_default()
+    }).as_ptr()
+}
+
+// Version of the config format this library reads and writes
+#[no_mangle]
+pub extern "C" fn clipboard_manager_config_version() -> u32 {
+    CONFIG_VERSION
+}
+
+// Updates the content only if its hash still equals `expected_hash`, as
+// returned by clipboard_manager_get_register_hash. On Conflict, if
+// `out_current_hash` is not null, it receives the current hash (free with
+// clipboard_manager_free_string); otherwise it is set to null.
+#[no_mangle]
+pub extern "C" fn clipboard_manager_update_register_content_cas(
+    manager: *mut ClipboardManager,
+    name: *const c_char,
+    expected_hash: *const c_char,
+    content: *const c_char,
+    out_current_hash: *mut *mut c_char
+) -> c_int {
+    if !out_current_hash.is_null() {
+        unsafe { *out_current_hash = std::ptr::null_mut(); }
     }
+    
+    ffi_status(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let name = unsafe { str_arg(name)? };
+        let expected_hash = unsafe { str_arg(expected_hash)? };
+        let content = unsafe { str_arg(content)? };
+        let result = manager.update_register_content_cas(name, expected_hash, content);
+        if result == Err(ClipboardError::Conflict) && !out_current_hash.is_null() {
+            if let Some(hash) = manager.get_register_hash(name) {
+                unsafe { *out_current_hash = into_c_string(hash); }
+            }
+        }
+        result
+    })
+}
+
+// Removes every unlocked register with no content. Returns the number
+// removed, or a negative error code.
+#[no_mangle]
+pub extern "C" fn clipboard_manager_remove_empty(
+    manager: *mut ClipboardManager
+) -> c_int {
+    ffi_int(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        manager.remove_empty().map(count_c_int)
+    })
+}
+
+// Returns [["a", "b"], ...], each inner array naming registers whose content
+// is identical, in display order
+#[no_mangle]
+pub extern "C" fn clipboard_manager_find_duplicates(
+    manager: *mut ClipboardManager
+) -> *mut c_char {
+    ffi_string(|| {
+        let manager = unsafe { manager_ref(manager).ok()? };
+        manager.find_duplicates()
+    })
+}
+
+// Removes all but one register of each duplicate group, keeping per `keep`:
+// 0 = the first listed, 1 = a pinned one, 2 = the most recently updated.
+// Locked registers are never removed. Returns the number removed, or a
+// negative error code (InvalidArgument for an unknown `keep`).
+#[no_mangle]
+pub extern "C" fn clipboard_manager_dedupe(
+    manager: *mut ClipboardManager,
+    keep: c_int
+) -> c_int {
+    ffi_int(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let keep = DedupeKeep::from_c_int(keep).ok_or(ClipboardError::InvalidArgument)?;
+        manager.dedupe(keep).map(count_c_int)
+    })
+}
+
+// Removes every unlocked register tagged `tag`. Returns the number removed,
+// or a negative error code.
+#[no_mangle]
+pub extern "C" fn clipboard_manager_remove_by_tag(
+    manager: *mut ClipboardManager,
+    tag: *const c_char
+) -> c_int {
+    ffi_int(|| {
+        let manager = unsafe { manager_ref(manager)? };
+        let tag = unsafe { str_arg(tag)? };
+        manager.remove_by_tag(tag).map(count_c_int)
+    })
 }
 
 #[no_mangle]
 pub extern "C" fn clipboard_manager_free_string(s: *mut c_char) {
     if !s.is_null() {
-        unsafe { CString::from_raw(s); }
+        unsafe { drop(CString::from_raw(s)); }
+    }
+}
+
+// `len` must be the length reported when the buffer was returned
+#[no_mangle]
+pub extern "C" fn clipboard_manager_free_bytes(ptr: *mut u8, len: usize) {
+    if !ptr.is_null() {
+        unsafe { drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(ptr, len))); }
     }
 }
 
@@ -341,3 +6775,791 @@ fn main() {
     // This is just a placeholder for testing
     // The actual functionality will be used from Swift via FFI
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+    
+    // Every FFI function taking a manager must fail cleanly on null instead
+    // of dereferencing it
+    #[test]
+    fn ffi_rejects_null_manager() {
+        let null: *mut ClipboardManager = ptr::null_mut();
+        let code = ClipboardError::NullPointer as c_int;
+        
+        assert_eq!(clipboard_manager_add_register(null, ptr::null(), ptr::null()), code);
+        assert_eq!(clipboard_manager_add_registers(null, ptr::null()), code);
+        assert_eq!(clipboard_manager_add_register_checked(null, ptr::null(), ptr::null()), code);
+        assert_eq!(clipboard_manager_add_register_validated(null, ptr::null(), ptr::null()), code);
+        assert_eq!(clipboard_manager_update_shortcut_validated(null, ptr::null(), ptr::null()), code);
+        assert_eq!(clipboard_manager_update_register_content(null, ptr::null(), ptr::null()), code);
+        assert_eq!(clipboard_manager_update_register_content_raw(null, ptr::null(), ptr::null()), code);
+        assert_eq!(clipboard_manager_set_normalize_on_store(null, 0), code);
+        assert_eq!(clipboard_manager_append_register_content(null, ptr::null(), ptr::null(), ptr::null()), code);
+        assert_eq!(clipboard_manager_clear_register_content(null, ptr::null()), code);
+        assert_eq!(clipboard_manager_clear_all_contents(null), code);
+        assert_eq!(clipboard_manager_update_register_binary(null, ptr::null(), ptr::null(), ptr::null(), 0), code);
+        assert_eq!(clipboard_manager_undo_register_content(null, ptr::null()), code);
+        assert_eq!(clipboard_manager_remove_register(null, ptr::null()), code);
+        assert_eq!(clipboard_manager_rename_register(null, ptr::null(), ptr::null()), code);
+        assert_eq!(clipboard_manager_duplicate_register(null, ptr::null(), ptr::null()), code);
+        assert_eq!(clipboard_manager_merge_registers(null, ptr::null(), ptr::null(), ptr::null()), code);
+        assert_eq!(clipboard_manager_swap_contents(null, ptr::null(), ptr::null()), code);
+        assert_eq!(clipboard_manager_update_shortcut(null, ptr::null(), ptr::null()), code);
+        assert_eq!(clipboard_manager_update_register(null, ptr::null(), ptr::null(), ptr::null()), code);
+        assert_eq!(clipboard_manager_update_shortcut_checked(null, ptr::null(), ptr::null()), code);
+        assert_eq!(clipboard_manager_set_max_registers(null, 0), code);
+        assert_eq!(clipboard_manager_set_max_content_bytes(null, 0), code);
+        assert_eq!(clipboard_manager_add_tag(null, ptr::null(), ptr::null()), code);
+        assert_eq!(clipboard_manager_remove_tag(null, ptr::null(), ptr::null()), code);
+        assert_eq!(clipboard_manager_set_history_limit(null, 0), code);
+        assert_eq!(clipboard_manager_move_register(null, ptr::null(), 0), code);
+        assert_eq!(clipboard_manager_promote(null, ptr::null()), code);
+        assert_eq!(clipboard_manager_register_count(null), code);
+        assert_eq!(clipboard_manager_is_first_run(null), code);
+        assert_eq!(clipboard_manager_contains_register(null, ptr::null()), code);
+        assert_eq!(clipboard_manager_mark_used(null, ptr::null()), code);
+        assert_eq!(clipboard_manager_total_content_bytes(null), code as i64);
+        assert_eq!(clipboard_manager_estimated_file_size(null), code as i64);
+        assert_eq!(clipboard_manager_get_use_count(null, ptr::null()), code as i64);
+        assert_eq!(clipboard_manager_set_hidden(null, ptr::null(), 0), code);
+        assert_eq!(clipboard_manager_set_locked(null, ptr::null(), 0), code);
+        assert_eq!(clipboard_manager_set_pinned(null, ptr::null(), 0), code);
+        assert_eq!(clipboard_manager_export_to(null, ptr::null()), code);
+        assert_eq!(clipboard_manager_import_from(null, ptr::null(), 0, 0), code);
+        assert_eq!(clipboard_manager_import_from_with(null, ptr::null(), 0, 0), code);
+        assert_eq!(clipboard_manager_import_csv(null, ptr::null(), 0, ptr::null_mut()), code);
+        assert_eq!(clipboard_manager_import_plist(null, ptr::null(), 0), code);
+        assert_eq!(clipboard_manager_export_jsonl(null, ptr::null()), code);
+        assert_eq!(clipboard_manager_import_jsonl(null, ptr::null(), 0, ptr::null_mut()), code);
+        assert_eq!(clipboard_manager_import_csv_with(null, ptr::null(), 0, 0, ptr::null_mut()), code);
+        assert_eq!(clipboard_manager_set_callback(null, None, ptr::null_mut()), code);
+        assert_eq!(clipboard_manager_set_callback_for(null, ptr::null(), None, ptr::null_mut()), code);
+        assert_eq!(clipboard_manager_flush(null), code);
+        assert_eq!(clipboard_manager_set_save_interval(null, 0), code);
+        assert_eq!(clipboard_manager_health_check(null), code);
+        assert_eq!(clipboard_manager_create_profile(null, ptr::null()), code);
+        assert_eq!(clipboard_manager_switch_profile(null, ptr::null()), code);
+        assert_eq!(clipboard_manager_delete_profile(null, ptr::null()), code);
+        assert_eq!(clipboard_manager_set_compression(null, 0), code);
+        assert_eq!(clipboard_manager_set_compact(null, 0), code);
+        assert_eq!(clipboard_manager_compact(null), code);
+        assert_eq!(clipboard_manager_restore_from_trash(null, ptr::null()), code);
+        assert_eq!(clipboard_manager_empty_trash(null), code);
+        assert_eq!(clipboard_manager_reload(null), code);
+        assert_eq!(clipboard_manager_enable_auto_reload(null), code);
+        assert_eq!(clipboard_manager_disable_auto_reload(null), code);
+        assert_eq!(clipboard_manager_set_format(null, ptr::null(), ptr::null()), code);
+        assert_eq!(clipboard_manager_move_register_to_profile(null, ptr::null(), ptr::null()), code);
+        assert_eq!(clipboard_manager_set_backup_count(null, 0), code);
+        assert_eq!(clipboard_manager_reset(null, 0), code);
+        assert_eq!(clipboard_manager_restore_backup(null, 0), code);
+        assert_eq!(clipboard_manager_set_register(null, ptr::null(), ptr::null(), ptr::null()), code);
+        assert_eq!(clipboard_manager_set_lock_timeout(null, 0), code);
+        assert_eq!(clipboard_manager_set_register_ttl(null, ptr::null(), 0), code);
+        assert_eq!(clipboard_manager_purge_expired(null), code);
+        assert_eq!(clipboard_manager_set_output_pretty(null, 0), code);
+        assert_eq!(clipboard_manager_restore_snapshot(null, ptr::null()), code);
+        assert_eq!(clipboard_manager_update_register_content_cas(null, ptr::null(), ptr::null(), ptr::null(), ptr::null_mut()), code);
+        assert_eq!(clipboard_manager_remove_empty(null), code);
+        assert_eq!(clipboard_manager_dedupe(null, 0), code);
+        assert_eq!(clipboard_manager_remove_by_tag(null, ptr::null()), code);
+        assert!(clipboard_manager_shortcut_in_use(null, ptr::null()).is_null());
+        assert!(clipboard_manager_replace_register_content(null, ptr::null(), ptr::null()).is_null());
+        assert!(clipboard_manager_get_register_content(null, ptr::null()).is_null());
+        assert!(clipboard_manager_get_register_content_ex(null, ptr::null(), ptr::null_mut()).is_null());
+        assert!(clipboard_manager_get_register_content_rendered(null, ptr::null()).is_null());
+        assert!(clipboard_manager_get_register_content_expanded(null, ptr::null()).is_null());
+        assert!(clipboard_manager_get_register_content_with_vars(null, ptr::null(), ptr::null()).is_null());
+        assert!(clipboard_manager_get_register(null, ptr::null()).is_null());
+        assert!(clipboard_manager_get_or_create(null, ptr::null(), ptr::null()).is_null());
+        assert!(clipboard_manager_get_register_history(null, ptr::null()).is_null());
+        assert!(clipboard_manager_search(null, ptr::null()).is_null());
+        assert!(clipboard_manager_search_with_mode(null, ptr::null(), 0).is_null());
+        assert!(clipboard_manager_search_fuzzy(null, ptr::null(), 0).is_null());
+        assert!(clipboard_manager_get_registers_by_tag(null, ptr::null()).is_null());
+        assert!(clipboard_manager_get_recently_used(null, 0).is_null());
+        assert!(clipboard_manager_get_most_used(null, 0).is_null());
+        assert!(clipboard_manager_register_names(null).is_null());
+        assert!(clipboard_manager_get_all_registers(null).is_null());
+        assert!(clipboard_manager_get_all_registers_preview(null, 0).is_null());
+        assert!(clipboard_manager_get_all_registers_including_hidden(null).is_null());
+        assert!(clipboard_manager_get_all_registers_v2(null).is_null());
+        assert!(clipboard_manager_export_csv(null).is_null());
+        assert!(clipboard_manager_export_plist(null).is_null());
+        assert!(clipboard_manager_active_profile(null).is_null());
+        assert!(clipboard_manager_list_profiles(null).is_null());
+        assert!(clipboard_manager_validate(null).is_null());
+        assert!(clipboard_manager_get_registers_page(null, 0, 0).is_null());
+        assert!(clipboard_manager_get_all_registers_sorted(null, 0).is_null());
+        assert!(clipboard_manager_get_registers_page_sorted(null, 0, 0, 0).is_null());
+        assert!(clipboard_manager_config_path(null).is_null());
+        assert!(clipboard_manager_get_shortcut(null, ptr::null()).is_null());
+        assert!(clipboard_manager_get_shortcut_map(null).is_null());
+        assert!(clipboard_manager_find_by_shortcut(null, ptr::null()).is_null());
+        assert!(clipboard_manager_get_trash(null).is_null());
+        assert!(clipboard_manager_diff_content(null, ptr::null(), ptr::null()).is_null());
+        assert!(clipboard_manager_validate_register(null, ptr::null()).is_null());
+        assert!(clipboard_manager_filter(null, ptr::null(), ptr::null()).is_null());
+        assert!(clipboard_manager_get_register_content_bytes(null, ptr::null(), ptr::null_mut()).is_null());
+        assert!(clipboard_manager_list_backups(null).is_null());
+        assert!(clipboard_manager_get_register_hash(null, ptr::null()).is_null());
+        assert!(clipboard_manager_snapshot(null).is_null());
+        assert!(clipboard_manager_apply_batch(null, ptr::null()).is_null());
+        assert!(clipboard_manager_add_register_unique(null, ptr::null(), ptr::null()).is_null());
+        assert!(clipboard_manager_get_register_stats(null, ptr::null()).is_null());
+        assert!(clipboard_manager_find_duplicates(null).is_null());
+        assert_eq!(clipboard_manager_revision(null), 0);
+        
+        clipboard_manager_destroy(null);
+        assert_eq!(clipboard_manager_is_alive(null), 0);
+    }
+    
+    #[test]
+    fn ffi_reports_invalid_utf8() {
+        let manager = clipboard_manager_new_in_memory();
+        let invalid = [b'a', 0xFF, b'b', 0];
+        let name = invalid.as_ptr() as *const c_char;
+        let code = ClipboardError::InvalidUtf8 as c_int;
+        
+        assert_eq!(clipboard_manager_add_register(manager, name, c"".as_ptr()), code);
+        assert_eq!(clipboard_manager_update_register_content(manager, c"a".as_ptr(), name), code);
+        assert!(clipboard_manager_get_register_content(manager, name).is_null());
+        
+        clipboard_manager_destroy(manager);
+    }
+    
+    #[test]
+    fn composed_and_decomposed_names_are_one_register() {
+        let mut state = ClipboardState::in_memory();
+        let composed = "caf\u{e9}";
+        let decomposed = "cafe\u{301}";
+        
+        state.add_register(decomposed.to_string(), String::new()).unwrap();
+        assert_eq!(state.add_register(composed.to_string(), String::new()), Err(ClipboardError::DuplicateName));
+        state.update_register_content(composed, "latte".to_string()).unwrap();
+        
+        assert_eq!(state.get_register_content(decomposed).as_deref(), Some("latte"));
+        assert_eq!(state.register_names(true), vec![composed.to_string()]);
+        // Only the normalization differs, so nothing is renamed
+        state.rename_register(composed, decomposed).unwrap();
+        assert_eq!(state.register_names(true), vec![composed.to_string()]);
+    }
+    
+    #[test]
+    fn clean_name_trims_tabs_and_unicode_whitespace() {
+        assert_eq!(clean_name("\tfoo\t"), Ok("foo".to_string()));
+        assert_eq!(clean_name("\u{3000}\u{a0}foo bar\u{2003}\n"), Ok("foo bar".to_string()));
+        assert_eq!(clean_name("\t\u{3000}\u{a0}"), Err(ClipboardError::InvalidName));
+        assert_eq!(clean_name(""), Err(ClipboardError::InvalidName));
+    }
+    
+    extern "C" fn record_name(name: *const c_char, user_data: *mut c_void) {
+        let names = unsafe { &*(user_data as *const Mutex<Vec<String>>) };
+        let name = unsafe { CStr::from_ptr(name) }.to_string_lossy().into_owned();
+        names.lock().unwrap().push(name);
+    }
+    
+    #[test]
+    fn callbacks_get_the_cleaned_name() {
+        let manager = ClipboardManager::new_in_memory();
+        let names: Mutex<Vec<String>> = Mutex::new(Vec::new());
+        manager.set_callback(Some(record_name), &names as *const _ as *mut c_void);
+        
+        manager.add_register(" \tfoo ", "").unwrap();
+        
+        assert_eq!(manager.get_register_content("foo").as_deref(), Some(""));
+        assert_eq!(*names.lock().unwrap(), vec!["foo".to_string()]);
+        manager.set_callback(None, ptr::null_mut());
+    }
+    
+    #[test]
+    fn validate_reports_a_broken_state() {
+        let config = br#"{"version": 1, "state": {
+            "registers": {
+                " ": {"content": "", "shortcut": ""},
+                "big": {"content": "0123456789", "shortcut": "cmd+1"},
+                "bad": {"content": "", "shortcut": "cmd+"},
+                "copy": {"content": "", "shortcut": "Cmd+1"}
+            },
+            "order": [" ", "big", "bad", "copy"],
+            "max_content_bytes": 4
+        }}"#;
+        let state = ClipboardState::from_config_json(config).unwrap();
+        
+        let issues = state.validate();
+        assert_eq!(issues.len(), 4, "{:?}", issues);
+        assert!(issues[0].contains("is empty"));
+        assert!(issues[1].contains("over the 4 byte limit"));
+        assert!(issues[2].contains("invalid shortcut"));
+        assert!(issues[3].contains("share the shortcut"));
+        
+        assert!(ClipboardState::in_memory().validate().is_empty());
+    }
+    
+    #[test]
+    fn expand_env_keeps_unset_variables_and_escapes() {
+        env::set_var("CLIPBOARD_TEST_USER", "ada");
+        env::remove_var("CLIPBOARD_TEST_UNSET");
+        
+        assert_eq!(expand_env("hi $CLIPBOARD_TEST_USER!"), "hi ada!");
+        assert_eq!(expand_env("${CLIPBOARD_TEST_USER}x"), "adax");
+        assert_eq!(expand_env("$CLIPBOARD_TEST_UNSET and ${CLIPBOARD_TEST_UNSET}"), "$CLIPBOARD_TEST_UNSET and ${CLIPBOARD_TEST_UNSET}");
+        assert_eq!(expand_env("costs \\$CLIPBOARD_TEST_USER"), "costs $CLIPBOARD_TEST_USER");
+        assert_eq!(expand_env("C:\\\\path \\n"), "C:\\\\path \\n");
+        assert_eq!(expand_env("$ ${} $1"), "$ ${} $1");
+    }
+    
+    #[test]
+    fn get_all_registers_v2_shape() {
+        let manager = ClipboardManager::new_in_memory();
+        manager.set_clock(Arc::new(ManualClock::new(1_700_000_000))).unwrap();
+        manager.add_register("greeting", "cmd+1").unwrap();
+        manager.update_register_content("greeting", "hello").unwrap();
+        let hash = manager.get_register_hash("greeting").unwrap();
+        
+        let json: serde_json::Value = serde_json::from_str(&manager.get_all_registers_v2().unwrap()).unwrap();
+        assert_eq!(json, serde_json::json!([{
+            "name": "greeting",
+            "content": "hello",
+            "shortcut": "cmd+1",
+            "created_at": 1_700_000_000,
+            "updated_at": 1_700_000_000,
+            "history": [],
+            "tags": [],
+            "pinned": false,
+            "last_used_at": null,
+            "use_count": 0,
+            "locked": false,
+            "format": null,
+            "expires_at": null,
+            "hidden": false,
+            "content_hash": hash
+        }]));
+    }
+    
+    #[test]
+    fn normalize_on_store_handles_mixed_line_endings() {
+        let mut state = ClipboardState::in_memory();
+        state.add_register("a".to_string(), String::new()).unwrap();
+        let pasted = "one  \r\ntwo\t\rthree \nfour\r\n";
+        
+        state.update_register_content("a", pasted.to_string()).unwrap();
+        assert_eq!(state.get_register_content("a").as_deref(), Some(pasted));
+        
+        state.set_normalize_on_store(true).unwrap();
+        state.update_register_content("a", pasted.to_string()).unwrap();
+        assert_eq!(state.get_register_content("a").as_deref(), Some("one\ntwo\nthree\nfour\n"));
+        
+        state.update_register_content_raw("a", pasted.to_string()).unwrap();
+        assert_eq!(state.get_register_content("a").as_deref(), Some(pasted));
+    }
+    
+    #[test]
+    fn natural_sort_compares_digit_runs_by_value() {
+        let mut state = ClipboardState::in_memory();
+        for name in ["file10", "file2", "File1", "file02b"] {
+            state.add_register(name.to_string(), String::new()).unwrap();
+        }
+        let names: Vec<String> = state.get_all_registers_sorted(SortOrder::Natural)
+            .into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, ["File1", "file2", "file02b", "file10"]);
+        
+        let names: Vec<String> = state.get_all_registers_sorted(SortOrder::Alphabetical)
+            .into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, ["file02b", "File1", "file10", "file2"]);
+    }
+    
+    #[test]
+    fn timestamps_follow_the_clock() {
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut state = ClipboardState::in_memory();
+        state.set_clock(clock.clone());
+        
+        state.add_register("a".to_string(), String::new()).unwrap();
+        let register = state.get_register("a").unwrap();
+        assert_eq!((register.created_at, register.updated_at, register.last_used_at), (Some(1_000), Some(1_000), None));
+        
+        clock.advance(60);
+        state.update_register_content("a", "text".to_string()).unwrap();
+        let register = state.get_register("a").unwrap();
+        assert_eq!((register.created_at, register.updated_at, register.last_used_at), (Some(1_000), Some(1_060), None));
+        
+        clock.set(5_000);
+        state.mark_used("a").unwrap();
+        let register = state.get_register("a").unwrap();
+        assert_eq!((register.created_at, register.updated_at, register.last_used_at), (Some(1_000), Some(1_060), Some(5_000)));
+        assert_eq!(register.use_count, 1);
+    }
+    
+    #[test]
+    fn make_room_evicts_nothing_when_it_cannot_make_enough() {
+        let manager = ClipboardManager::new_in_memory();
+        for name in ["a", "b", "c", "d"] {
+            manager.add_register(name, "").unwrap();
+        }
+        manager.set_locked("a", true).unwrap();
+        manager.set_locked("b", true).unwrap();
+        manager.set_max_registers(Some(2)).unwrap();
+        let names: Mutex<Vec<String>> = Mutex::new(Vec::new());
+        manager.set_callback(Some(record_name), &names as *const _ as *mut c_void);
+        
+        // Three would have to go but only c and d may
+        assert_eq!(manager.add_register("e", ""), Err(ClipboardError::RegisterLimit));
+        assert_eq!(manager.read_state().unwrap().register_names(true), ["a", "b", "c", "d"]);
+        assert!(names.lock().unwrap().is_empty());
+        
+        manager.set_max_registers(Some(3)).unwrap();
+        manager.add_register("e", "").unwrap();
+        assert_eq!(manager.read_state().unwrap().register_names(true), ["a", "b", "e"]);
+        manager.set_callback(None, ptr::null_mut());
+        
+        assert_eq!(manager.set_max_registers(Some(0)), Err(ClipboardError::InvalidArgument));
+    }
+    
+    #[test]
+    fn import_from_leaves_locked_registers_alone() {
+        let mut source = ClipboardState::in_memory();
+        for (name, content) in [("keep", "theirs"), ("new", "new")] {
+            source.add_register(name.to_string(), String::new()).unwrap();
+            source.update_register_content(name, content.to_string()).unwrap();
+        }
+        let path = std::env::temp_dir().join(format!("clipboard-import-{}.json", std::process::id()));
+        source.export_to(&path).unwrap();
+        
+        let mut state = ClipboardState::in_memory();
+        for name in ["keep", "drop"] {
+            state.add_register(name.to_string(), String::new()).unwrap();
+            state.update_register_content(name, "mine".to_string()).unwrap();
+        }
+        state.set_locked("keep", true).unwrap();
+        
+        state.import_from(&path, false, true).unwrap();
+        assert_eq!(state.get_register_content("keep").as_deref(), Some("mine"));
+        assert_eq!(state.register_names(true), ["drop", "keep", "new"]);
+        
+        state.import_from(&path, true, true).unwrap();
+        assert_eq!(state.get_register_content("keep").as_deref(), Some("mine"));
+        assert_eq!(state.register_names(true), ["keep", "new"]);
+        fs::remove_file(&path).unwrap();
+    }
+    
+    #[test]
+    fn import_csv_replace_keeps_locked_registers() {
+        let mut state = ClipboardState::in_memory();
+        for name in ["keep", "drop"] {
+            state.add_register(name.to_string(), String::new()).unwrap();
+            state.update_register_content(name, "mine".to_string()).unwrap();
+        }
+        state.set_locked("keep", true).unwrap();
+        
+        let data = "name,shortcut,content\nkeep,,theirs\nnew,,new\n";
+        assert_eq!(state.import_csv_with(data, true, OnConflict::Overwrite), Ok(1));
+        assert_eq!(state.get_register_content("keep").as_deref(), Some("mine"));
+        assert!(state.get_register("keep").unwrap().locked);
+        assert_eq!(state.register_names(true), ["keep", "new"]);
+    }
+    
+    #[test]
+    fn plist_round_trip_keeps_order_and_locked_registers() {
+        let mut source = ClipboardState::in_memory();
+        for name in ["a", "b", "c"] {
+            source.add_register(name.to_string(), String::new()).unwrap();
+        }
+        source.set_pinned("c", true).unwrap();
+        let plist = source.export_plist().unwrap();
+        
+        let mut state = ClipboardState::in_memory();
+        for name in ["keep", "drop"] {
+            state.add_register(name.to_string(), String::new()).unwrap();
+        }
+        state.set_locked("keep", true).unwrap();
+        assert_eq!(state.import_plist(&plist, true), Ok(3));
+        assert_eq!(state.order, ["keep", "a", "b", "c"]);
+        assert!(state.get_register("c").unwrap().pinned);
+    }
+    
+    #[test]
+    fn jsonl_round_trip_keeps_order_and_locked_registers() {
+        let mut source = ClipboardState::in_memory();
+        for name in ["a", "b", "c"] {
+            source.add_register(name.to_string(), String::new()).unwrap();
+        }
+        source.set_pinned("c", true).unwrap();
+        let mut jsonl = Vec::new();
+        source.export_jsonl(&mut jsonl).unwrap();
+        
+        let mut state = ClipboardState::in_memory();
+        for name in ["keep", "drop"] {
+            state.add_register(name.to_string(), String::new()).unwrap();
+        }
+        state.set_locked("keep", true).unwrap();
+        assert_eq!(state.import_jsonl(jsonl.as_slice(), true), Ok(3));
+        assert_eq!(state.order, ["keep", "a", "b", "c"]);
+        assert!(state.get_register("c").unwrap().pinned);
+    }
+    
+    #[test]
+    fn hidden_registers_are_left_out_of_searches_and_listings() {
+        let mut state = ClipboardState::in_memory();
+        for name in ["shown", "secret"] {
+            state.add_register(name.to_string(), String::new()).unwrap();
+            state.update_register_content(name, "token".to_string()).unwrap();
+            state.add_tag(name, "work").unwrap();
+            state.mark_used(name).unwrap();
+        }
+        state.set_hidden("secret", true).unwrap();
+        
+        let names = |registers: Vec<(String, ClipboardRegister)>| -> Vec<String> {
+            registers.into_iter().map(|(name, _)| name).collect()
+        };
+        for include_hidden in [false, true] {
+            let expected: &[&str] = if include_hidden { &["secret", "shown"] } else { &["shown"] };
+            assert_eq!(names(state.search("token", include_hidden)), expected);
+            assert_eq!(names(state.search_with_mode("s", SearchMode::Prefix, include_hidden)), expected);
+            let fuzzy: Vec<String> = state.search_fuzzy("tokn", 1, include_hidden).into_iter()
+                .map(|(name, _, _)| name).collect();
+            assert_eq!(fuzzy, expected);
+            assert_eq!(names(state.filter(Some("work"), None, include_hidden)), expected);
+            assert_eq!(names(state.get_registers_by_tag("work", include_hidden)), expected);
+            assert_eq!(names(state.get_most_used(10, include_hidden)), expected);
+            assert_eq!(state.get_recently_used(10, include_hidden).len(), expected.len());
+            assert_eq!(state.register_names(include_hidden), expected);
+        }
+    }
+    
+    #[test]
+    fn update_register_binary_refuses_plain_text() {
+        let mut state = ClipboardState::in_memory();
+        state.add_register("a".to_string(), String::new()).unwrap();
+        state.update_register_content("a", "text".to_string()).unwrap();
+        
+        assert_eq!(state.update_register_binary("a", TEXT_PLAIN, b"hello"), Err(ClipboardError::InvalidArgument));
+        assert_eq!(state.get_register_content("a").as_deref(), Some("text"));
+        
+        state.update_register_binary("a", "image/png", &[0x89, 0x50]).unwrap();
+        assert_eq!(state.get_register_binary("a"), Some(vec![0x89, 0x50]));
+    }
+    
+    #[test]
+    fn merge_registers_trashes_the_source() {
+        let mut state = ClipboardState::in_memory();
+        for (name, content) in [("target", "one"), ("source", "two")] {
+            state.add_register(name.to_string(), String::new()).unwrap();
+            state.update_register_content(name, content.to_string()).unwrap();
+        }
+        
+        state.merge_registers("target", "source", "\n").unwrap();
+        assert_eq!(state.get_register_content("target").as_deref(), Some("one\ntwo"));
+        assert!(!state.contains_register("source"));
+        
+        state.restore_from_trash("source").unwrap();
+        assert_eq!(state.get_register_content("source").as_deref(), Some("two"));
+    }
+    
+    #[test]
+    fn profile_names_are_cleaned_like_create_profile() {
+        let mut state = ClipboardState::in_memory();
+        state.create_profile(" work\t").unwrap();
+        
+        state.switch_profile("work ").unwrap();
+        assert_eq!(state.active_profile(), "work");
+        state.switch_profile(DEFAULT_PROFILE).unwrap();
+        
+        assert_eq!(state.delete_profile("\u{a0}work"), Ok(()));
+        assert_eq!(state.list_profiles().len(), 1);
+        assert_eq!(state.switch_profile(" "), Err(ClipboardError::InvalidName));
+    }
+    
+    #[test]
+    fn move_register_to_profile_cleans_the_profile_name() {
+        let mut state = ClipboardState::in_memory();
+        state.create_profile("work").unwrap();
+        state.add_register("a".to_string(), String::new()).unwrap();
+        
+        state.move_register_to_profile("a", " work\n").unwrap();
+        assert!(!state.contains_register("a"));
+        state.switch_profile("work").unwrap();
+        assert!(state.contains_register("a"));
+    }
+    
+    #[test]
+    fn settings_bump_the_revision() {
+        let manager = ClipboardManager::new_in_memory();
+        manager.add_register("a", "").unwrap();
+        let names: Mutex<Vec<String>> = Mutex::new(Vec::new());
+        manager.set_callback(Some(record_name), &names as *const _ as *mut c_void);
+        
+        let before = manager.revision();
+        manager.set_max_registers(Some(10)).unwrap();
+        manager.set_normalize_on_store(true).unwrap();
+        manager.set_max_content_bytes(Some(100)).unwrap();
+        manager.set_compression(true).unwrap();
+        manager.set_compact(true).unwrap();
+        manager.set_backup_count(2).unwrap();
+        assert_eq!(manager.revision(), before + 6);
+        assert!(names.lock().unwrap().is_empty());
+        
+        manager.set_history_limit(5).unwrap();
+        manager.compact().unwrap();
+        assert_eq!(manager.revision(), before + 8);
+        assert_eq!(*names.lock().unwrap(), ["", ""]);
+        manager.set_callback(None, ptr::null_mut());
+    }
+    
+    #[test]
+    fn imports_report_the_specific_error() {
+        let mut state = ClipboardState::in_memory();
+        let malformed = |line, error| Err(ImportError { line: Some(line), error });
+        assert_eq!(state.import_csv("a,,x\n\" \",,y\n", false), malformed(2, ClipboardError::InvalidName));
+        assert_eq!(state.import_csv("a,,x\nb,x\n", false), malformed(2, ClipboardError::InvalidFormat));
+        assert_eq!(state.import_jsonl("\n{\"name\": \"a\"\n".as_bytes(), false), malformed(2, ClipboardError::InvalidFormat));
+        assert!(state.is_empty());
+        
+        let manager = clipboard_manager_new_in_memory();
+        let missing = CString::new(std::env::temp_dir().join("clipboard-missing.json").to_str().unwrap()).unwrap();
+        assert_eq!(clipboard_manager_import_from(manager, missing.as_ptr(), 0, 0), ClipboardError::Io as c_int);
+        let mut line = -1;
+        assert_eq!(clipboard_manager_import_csv(manager, c"a,,x\n\" \",,x".as_ptr(), 0, &mut line), ClipboardError::InvalidName as c_int);
+        assert_eq!(line, 2);
+        assert_eq!(clipboard_manager_import_csv_with(manager, c"a,,x\nb,x".as_ptr(), 0, 0, &mut line), ClipboardError::InvalidFormat as c_int);
+        assert_eq!(line, 2);
+        assert_eq!(clipboard_manager_import_csv(manager, c"a,,x".as_ptr(), 0, &mut line), 1);
+        assert_eq!(line, 0);
+        clipboard_manager_destroy(manager);
+    }
+    
+    #[test]
+    fn reset_keeps_how_the_config_is_saved() {
+        let mut state = ClipboardState::in_memory();
+        state.add_register("a".to_string(), String::new()).unwrap();
+        state.set_compression(true).unwrap();
+        state.set_compact(true).unwrap();
+        state.set_history_limit(3).unwrap();
+        
+        state.reset(true).unwrap();
+        assert!(state.is_empty());
+        assert!(state.compress && state.compact);
+        assert_eq!(state.backup_count, 0);
+        assert_ne!(state.history_limit, 3);
+    }
+    
+    #[test]
+    fn add_registers_normalizes_and_limits_content() {
+        let mut state = ClipboardState::in_memory();
+        state.set_normalize_on_store(true).unwrap();
+        state.set_max_content_bytes(Some(4)).unwrap();
+        let entries = vec![
+            ("a".to_string(), String::new(), "ab  \r\n".to_string()),
+            ("b".to_string(), String::new(), "too long".to_string()),
+        ];
+        
+        assert_eq!(state.add_registers(entries), Ok(1));
+        assert_eq!(state.get_register_content("a").as_deref(), Some("ab\n"));
+        assert!(!state.contains_register("b"));
+        assert_eq!(count_c_int(usize::MAX), c_int::MAX);
+    }
+    
+    #[test]
+    fn failed_saves_reach_the_caller() {
+        let path = std::env::temp_dir().join(format!("clipboard-missing-dir-{}", std::process::id())).join("config.json");
+        let manager = ClipboardManager::new(Some(path)).unwrap();
+        manager.set_save_interval(Some(Duration::from_secs(3600))).unwrap();
+        
+        manager.add_register("a", "").unwrap();
+        assert_eq!(manager.flush(), Err(ClipboardError::SaveFailed));
+        assert_eq!(manager.health_check(), Err(ClipboardError::SaveFailed));
+        assert_eq!(manager.set_save_interval(None), Err(ClipboardError::SaveFailed));
+        assert_eq!(manager.add_register("b", ""), Err(ClipboardError::SaveFailed));
+    }
+    
+    #[test]
+    fn ffi_import_jsonl_reports_the_malformed_line() {
+        let path = std::env::temp_dir().join(format!("clipboard-import-{}.jsonl", std::process::id()));
+        fs::write(&path, "{\"name\": \"a\", \"content\": \"\", \"shortcut\": \"\"}\n\n{\"name\": \n").unwrap();
+        let c_path = CString::new(path.to_str().unwrap()).unwrap();
+        let manager = clipboard_manager_new_in_memory();
+        let mut line = -1;
+        
+        assert_eq!(clipboard_manager_import_jsonl(manager, c_path.as_ptr(), 0, &mut line), ClipboardError::InvalidFormat as c_int);
+        assert_eq!(line, 3);
+        
+        fs::write(&path, "{\"name\": \"a\", \"content\": \"\", \"shortcut\": \"\"}\n").unwrap();
+        assert_eq!(clipboard_manager_import_jsonl(manager, c_path.as_ptr(), 0, &mut line), 1);
+        assert_eq!(line, 0);
+        assert_eq!(clipboard_manager_import_jsonl(manager, c_path.as_ptr(), 0, ptr::null_mut()), 0);
+        clipboard_manager_destroy(manager);
+        fs::remove_file(&path).unwrap();
+    }
+    
+    #[test]
+    fn unversioned_config_migrates_cleanly() {
+        let v0 = br#"{"registers": {
+            "work": {"content": "line one\nline two", "shortcut": "cmd+shift+w"},
+            "email": {"content": "me@example.com", "shortcut": "cmd+e"},
+            "empty": {"content": "", "shortcut": ""}
+        }}"#;
+        let state = ClipboardState::from_config_json(v0).unwrap();
+        
+        assert_eq!(state.order, ["email", "empty", "work"]);
+        let expected = [("email", "me@example.com", "cmd+e"), ("empty", "", ""), ("work", "line one\nline two", "cmd+shift+w")];
+        for (name, content, shortcut) in expected {
+            let register = state.get_register(name).unwrap();
+            assert_eq!((register.content.as_str(), register.shortcut.as_str()), (content, shortcut));
+            assert!(register.history.is_empty() && register.tags.is_empty());
+            assert!(!register.pinned && !register.locked && !register.hidden);
+            assert_eq!(register.content_type, TEXT_PLAIN);
+        }
+        assert_eq!(state.active_profile(), DEFAULT_PROFILE);
+        assert!(state.validate().is_empty());
+        
+        let saved: serde_json::Value = serde_json::from_str(&state.to_config_json().unwrap()).unwrap();
+        assert_eq!(saved["version"], CONFIG_VERSION);
+        let reloaded = ClipboardState::from_config_json(saved.to_string().as_bytes()).unwrap();
+        assert_eq!(reloaded.order, state.order);
+        assert_eq!(reloaded.get_register("work").unwrap().content, "line one\nline two");
+    }
+    
+    #[test]
+    fn previews_leave_out_history() {
+        let mut state = ClipboardState::in_memory();
+        state.add_register("a".to_string(), String::new()).unwrap();
+        state.update_register_content("a", "x".repeat(5000)).unwrap();
+        state.update_register_content("a", "short".to_string()).unwrap();
+        assert!(!state.get_register("a").unwrap().history.is_empty());
+        
+        let previews = state.get_all_registers_preview(3);
+        assert_eq!(previews[0].1.register.content, "sho\u{2026}");
+        assert!(previews[0].1.truncated);
+        let json = serde_json::to_value(&previews[0].1).unwrap();
+        assert_eq!(json["history"], serde_json::json!([]));
+    }
+    
+    #[test]
+    fn apply_batch_json_reports_parse_errors() {
+        let manager = ClipboardManager::new_in_memory();
+        let report: serde_json::Value = serde_json::from_str(&manager.apply_batch_json("[{\"op\": 1}").unwrap()).unwrap();
+        
+        assert_eq!(report["ok"], false);
+        assert_eq!(report["error"], ClipboardError::InvalidFormat as c_int);
+        assert_eq!(report["results"], serde_json::json!([]));
+        let message = report["message"].as_str().unwrap();
+        assert!(message.starts_with("invalid batch: ") && message.contains("line 1"), "{}", message);
+    }
+    
+    #[test]
+    fn imports_respect_the_register_limit() {
+        let clock = Arc::new(ManualClock::new(100));
+        let mut state = ClipboardState::in_memory();
+        state.set_clock(clock.clone());
+        for name in ["old", "keep"] {
+            state.add_register(name.to_string(), String::new()).unwrap();
+        }
+        state.set_locked("keep", true).unwrap();
+        state.set_max_registers(Some(3)).unwrap();
+        clock.advance(100);
+        
+        // "old" makes way for "b", then "a" for "c"
+        assert_eq!(state.import_csv("a,,\nb,,\nc,,\n", false), Ok(3));
+        assert_eq!(state.register_names(true), ["b", "c", "keep"]);
+        assert_eq!(state.evicted, ["old", "a"]);
+        
+        state.set_locked("b", true).unwrap();
+        state.set_locked("c", true).unwrap();
+        assert_eq!(state.import_csv("d,,\n", false), Ok(0));
+        assert_eq!(state.register_names(true), ["b", "c", "keep"]);
+    }
+    
+    #[test]
+    fn imports_skip_content_over_the_size_limit() {
+        let mut source = ClipboardState::in_memory();
+        for (name, content) in [("small", "ok"), ("big", "far too long")] {
+            source.add_register(name.to_string(), String::new()).unwrap();
+            source.update_register_content(name, content.to_string()).unwrap();
+        }
+        let plist = source.export_plist().unwrap();
+        let mut jsonl = Vec::new();
+        source.export_jsonl(&mut jsonl).unwrap();
+        
+        let mut state = ClipboardState::in_memory();
+        state.set_max_content_bytes(Some(4)).unwrap();
+        assert_eq!(state.import_csv("small,,ok\nbig,,far too long\n", true), Ok(1));
+        assert_eq!(state.import_plist(&plist, true), Ok(1));
+        assert_eq!(state.import_jsonl(jsonl.as_slice(), true), Ok(1));
+        assert_eq!(state.register_names(true), ["small"]);
+    }
+    
+    #[test]
+    fn imports_normalize_content() {
+        let mut state = ClipboardState::in_memory();
+        state.set_normalize_on_store(true).unwrap();
+        state.set_max_content_bytes(Some(4)).unwrap();
+        
+        assert_eq!(state.import_csv("a,,\"ab  \r\n\"\n", false), Ok(1));
+        assert_eq!(state.get_register_content("a").as_deref(), Some("ab\n"));
+    }
+    
+    #[test]
+    fn unchanged_content_is_not_a_change() {
+        let manager = ClipboardManager::new_in_memory();
+        manager.add_register("a", "").unwrap();
+        manager.update_register_content("a", "text").unwrap();
+        let names: Mutex<Vec<String>> = Mutex::new(Vec::new());
+        manager.set_callback(Some(record_name), &names as *const _ as *mut c_void);
+        let revision = manager.revision();
+        
+        manager.update_register_content("a", "text").unwrap();
+        manager.append_register_content("a", "", None).unwrap();
+        
+        assert_eq!(manager.revision(), revision);
+        assert!(names.lock().unwrap().is_empty());
+        manager.update_register_content("a", "new").unwrap();
+        assert_eq!(manager.revision(), revision + 1);
+        assert_eq!(*names.lock().unwrap(), vec!["a".to_string()]);
+        manager.set_callback(None, ptr::null_mut());
+    }
+    
+    #[test]
+    fn registers_expire_on_the_clock() {
+        let clock = Arc::new(ManualClock::new(1_000));
+        let mut state = ClipboardState::in_memory();
+        state.set_clock(clock.clone());
+        state.add_register("otp".to_string(), String::new()).unwrap();
+        state.add_register("keep".to_string(), String::new()).unwrap();
+        
+        state.set_register_ttl("otp", Some(30)).unwrap();
+        assert_eq!(state.get_register("otp").unwrap().expires_at, Some(1_030));
+        assert_eq!(state.get_register("keep").unwrap().expires_at, None);
+        
+        clock.advance(29);
+        assert_eq!(state.purge_expired(), Ok(0));
+        clock.advance(1);
+        assert_eq!(state.purge_expired(), Ok(1));
+        assert!(!state.contains_register("otp"));
+        
+        clock.set(u64::MAX);
+        assert_eq!(state.purge_expired(), Ok(0));
+        assert!(state.contains_register("keep"));
+    }
+    
+    #[test]
+    fn reset_backup_leaves_backup_count_alone() {
+        let dir = std::env::temp_dir().join(format!("clipboard-reset-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.json");
+        let manager = ClipboardManager::new(Some(path.clone())).unwrap();
+        manager.add_register("a", "").unwrap();
+        
+        manager.reset(true).unwrap();
+        assert!(backup_path(&path, 1).is_file());
+        assert_eq!(manager.read_state().unwrap().backup_count, 0);
+        manager.add_register("b", "").unwrap();
+        assert!(!backup_path(&path, 2).exists());
+        
+        manager.restore_backup(1).unwrap();
+        assert!(manager.get_register_content("a").is_some());
+        assert!(manager.get_register_content("b").is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}